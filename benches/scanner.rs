@@ -0,0 +1,42 @@
+// Scans sources of growing size and prints how long it took. The time per
+// megabyte stays about the same as the source grows since scanning is
+// linear.
+//
+//   $ cargo bench --bench scanner
+use rulox::diagnostic::diagnostic::Diagnostics;
+use rulox::lexer::scanner::Scanner;
+
+use std::time::Instant;
+
+// a bit of everything the scanner handles, multi-byte characters included
+const CHUNK: &str = r#"
+// greet everyone, ünïcödé in comments is skipped as is
+fun greet(name, times) {
+    for (var i = 0; i < times; i = i + 1) {
+        if (i >= 2 and name != nil) print "héllo, " + name + " ✓";
+    }
+    return times * 3.25 - 1;
+}
+class Point < Base { init(x, y) { this.x = x; this.y = !y; } }
+"#;
+
+const MEGABYTE: usize = 1024 * 1024;
+
+fn main() {
+    let mut first = None;
+    for megabytes in [1, 2, 4, 8, 16] {
+        let source = CHUNK.repeat(megabytes * MEGABYTE / CHUNK.len());
+
+        let mut diagnostics = Diagnostics::new();
+        let start = Instant::now();
+        let tokens = Scanner::new(source, &mut diagnostics).scan_tokens();
+        let elapsed = start.elapsed();
+        assert!(diagnostics.is_empty());
+
+        let per_megabyte = elapsed.as_secs_f64() / megabytes as f64;
+        let ratio = per_megabyte / *first.get_or_insert(per_megabyte);
+        println!("{:>2} MB: {:>10.2?} {:>8} tokens, {:.2}x the time per MB \
+                  of 1 MB",
+                 megabytes, elapsed, tokens.len(), ratio);
+    }
+}
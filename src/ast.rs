@@ -1,2 +1,3 @@
-pub mod node; 
-
+pub mod ast_printer;
+pub mod node;
+pub mod slot;
@@ -0,0 +1,206 @@
+use crate::token::token_type::{self, TokenType};
+
+use super::node::*;
+
+// Prints the syntax tree as nested s-expressions, e.g. `(+ 1 (* 2 3))`.
+// Expressions stay on one line, statements nested in blocks, loops,
+// functions and classes go on their own lines, indented under their parent.
+#[derive(Default)]
+pub struct AstPrinter;
+
+impl AstPrinter {
+    pub fn new() -> Self {
+        AstPrinter
+    }
+
+    pub fn print(&mut self, stmts: &[Stmt]) -> String {
+        stmts.iter()
+            .map(|stmt| stmt.accept(self))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn print_expr(&mut self, expr: &Expr) -> String {
+        expr.accept(self)
+    }
+
+    fn parenthesize(&mut self, name: &str, exprs: &[&Expr]) -> String {
+        let mut out = format!("({}", name);
+        for expr in exprs {
+            out += " ";
+            out += &expr.accept(self);
+        }
+        out + ")"
+    }
+
+    // `head` followed by every child on its own line
+    fn nest(&mut self, head: String, children: Vec<String>) -> String {
+        let mut out = format!("({}", head);
+        for child in children {
+            for line in child.lines() {
+                out += "\n  ";
+                out += line;
+            }
+        }
+        out + ")"
+    }
+
+    fn function(&mut self, function: &Function) -> String {
+        let params: Vec<&str> = function.params.iter()
+            .map(|param| &param.lexeme[..])
+            .collect();
+        let head = format!("fun {} ({})", function.name.lexeme,
+                           params.join(" "));
+        let body = function.body.iter().map(|stmt| stmt.accept(self)).collect();
+        self.nest(head, body)
+    }
+}
+
+impl Visitor for AstPrinter {
+    type R = String;
+
+    fn visit_assign (&mut self, assign: &Assign) -> Self::R {
+        let name = format!("= {}", assign.name.lexeme);
+        self.parenthesize(&name, &[&assign.value])
+    }
+
+    fn visit_binary (&mut self, binary: &Binary) -> Self::R {
+        self.parenthesize(&binary.operator.lexeme,
+                          &[&binary.left, &binary.right])
+    }
+
+    fn visit_call (&mut self, call: &Call) -> Self::R {
+        let mut exprs = vec![&*call.callee];
+        exprs.extend(call.arguments.iter().map(|argument| &**argument));
+        self.parenthesize("call", &exprs)
+    }
+
+    fn visit_get (&mut self, get: &Get) -> Self::R {
+        let object = get.object.accept(self);
+        format!("(. {} {})", object, get.name.lexeme)
+    }
+
+    fn visit_grouping (&mut self, grouping: &Grouping) -> Self::R {
+        self.parenthesize("group", &[&grouping.expression])
+    }
+
+    fn visit_interpolation (&mut self, interpolation: &Interpolation)
+                            -> Self::R {
+        let parts: Vec<&Expr> = interpolation.parts.iter()
+            .map(|part| &**part)
+            .collect();
+        self.parenthesize("interpolate", &parts)
+    }
+
+    // strings are quoted to tell them apart from variables
+    fn visit_literal (&mut self, literal: &Literal) -> Self::R {
+        match &literal.value.token_type {
+            TokenType::LITERAL(token_type::Literal::STRING(s)) =>
+                format!("{:?}", s),
+            _ => literal.value.lexeme.clone(),
+        }
+    }
+
+    fn visit_logical (&mut self, logical: &Logical) -> Self::R {
+        self.parenthesize(&logical.operator.lexeme,
+                          &[&logical.left, &logical.right])
+    }
+
+    fn visit_set (&mut self, set: &Set) -> Self::R {
+        let object = set.object.accept(self);
+        let value = set.value.accept(self);
+        format!("(= (. {} {}) {})", object, set.name.lexeme, value)
+    }
+
+    fn visit_super (&mut self, super_: &Super) -> Self::R {
+        format!("(super {})", super_.method.lexeme)
+    }
+
+    fn visit_this (&mut self, _this: &This) -> Self::R {
+        String::from("this")
+    }
+
+    fn visit_unary (&mut self, unary: &Unary) -> Self::R {
+        self.parenthesize(&unary.operator.lexeme, &[&unary.right])
+    }
+
+    fn visit_variable (&mut self, variable: &Variable) -> Self::R {
+        variable.name.lexeme.clone()
+    }
+}
+
+impl StmtVisitor for AstPrinter {
+    type R = String;
+
+    fn visit_expression (&mut self, expression: &Expression) -> Self::R {
+        self.parenthesize(";", &[&expression.expression])
+    }
+
+    fn visit_print (&mut self, print: &Print) -> Self::R {
+        self.parenthesize("print", &[&print.expression])
+    }
+
+    fn visit_var (&mut self, var: &Var) -> Self::R {
+        let name = format!("var {}", var.name.lexeme);
+        match &var.initializer {
+            Some(initializer) => self.parenthesize(&name, &[initializer]),
+            None => format!("({})", name),
+        }
+    }
+
+    fn visit_block (&mut self, block: &Block) -> Self::R {
+        let children = block.statements.iter()
+            .map(|stmt| stmt.accept(self))
+            .collect();
+        self.nest(String::from("block"), children)
+    }
+
+    fn visit_if (&mut self, if_: &If) -> Self::R {
+        let head = format!("if {}", if_.condition.accept(self));
+        let mut children = vec![if_.then_branch.accept(self)];
+        if let Some(else_branch) = &if_.else_branch {
+            children.push(else_branch.accept(self));
+        }
+        self.nest(head, children)
+    }
+
+    // `for` loops are parsed into a `while` with an increment
+    fn visit_while (&mut self, while_: &While) -> Self::R {
+        let head = format!("while {}", while_.condition.accept(self));
+        let mut children = vec![while_.body.accept(self)];
+        if let Some(increment) = &while_.increment {
+            children.push(self.parenthesize("increment", &[increment]));
+        }
+        self.nest(head, children)
+    }
+
+    fn visit_break (&mut self, _break_: &Break) -> Self::R {
+        String::from("(break)")
+    }
+
+    fn visit_continue (&mut self, _continue_: &Continue) -> Self::R {
+        String::from("(continue)")
+    }
+
+    fn visit_function (&mut self, function: &Function) -> Self::R {
+        self.function(function)
+    }
+
+    fn visit_return (&mut self, return_: &Return) -> Self::R {
+        match &return_.value {
+            Some(value) => self.parenthesize("return", &[value]),
+            None => String::from("(return)"),
+        }
+    }
+
+    fn visit_class (&mut self, class: &Class) -> Self::R {
+        let mut head = format!("class {}", class.name.lexeme);
+        if let Some(superclass) = &class.superclass {
+            head += &format!(" < {}", superclass.accept(self));
+        }
+        let methods = class.methods.iter()
+            .map(|method| self.function(method))
+            .collect();
+        self.nest(head, methods)
+    }
+}
@@ -1,10 +1,22 @@
+use crate::ast::slot::Slot;
+use crate::token::span::Span;
 use crate::token::token::Token;
 
+use std::cell::Cell;
+use std::rc::Rc;
+
 pub enum Expr {
     Assign(Assign),
     Binary(Binary),
+    Call(Call),
+    Get(Get),
     Grouping(Grouping),
+    Interpolation(Interpolation),
     Literal(Literal),
+    Logical(Logical),
+    Set(Set),
+    Super(Super),
+    This(This),
     Unary(Unary),
     Variable(Variable),
 }
@@ -14,20 +26,52 @@ impl Expr {
         match self {
             Expr::Assign(assign) => visitor.visit_assign(assign),
             Expr::Binary(binary) => visitor.visit_binary(binary),
+            Expr::Call(call) => visitor.visit_call(call),
+            Expr::Get(get) => visitor.visit_get(get),
             Expr::Grouping(grouping) => visitor.visit_grouping(grouping),
+            Expr::Interpolation(interpolation) => visitor.visit_interpolation(interpolation),
             Expr::Literal(literal) => visitor.visit_literal(literal),
+            Expr::Logical(logical) => visitor.visit_logical(logical),
+            Expr::Set(set) => visitor.visit_set(set),
+            Expr::Super(super_) => visitor.visit_super(super_),
+            Expr::This(this) => visitor.visit_this(this),
             Expr::Unary(unary) => visitor.visit_unary(unary),
             Expr::Variable(variable) => visitor.visit_variable(variable),
         }
     }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Assign(assign) => assign.span,
+            Expr::Binary(binary) => binary.span,
+            Expr::Call(call) => call.span,
+            Expr::Get(get) => get.span,
+            Expr::Grouping(grouping) => grouping.span,
+            Expr::Interpolation(interpolation) => interpolation.span,
+            Expr::Literal(literal) => literal.span,
+            Expr::Logical(logical) => logical.span,
+            Expr::Set(set) => set.span,
+            Expr::Super(super_) => super_.span,
+            Expr::This(this) => this.span,
+            Expr::Unary(unary) => unary.span,
+            Expr::Variable(variable) => variable.span,
+        }
+    }
 }
 
 pub trait Visitor {
     type R;
     fn visit_assign (&mut self, assign: &Assign) -> Self::R;
     fn visit_binary (&mut self, binary: &Binary) -> Self::R;
+    fn visit_call (&mut self, call: &Call) -> Self::R;
+    fn visit_get (&mut self, get: &Get) -> Self::R;
     fn visit_grouping (&mut self, grouping: &Grouping) -> Self::R;
+    fn visit_interpolation (&mut self, interpolation: &Interpolation) -> Self::R;
     fn visit_literal (&mut self, literal: &Literal) -> Self::R;
+    fn visit_logical (&mut self, logical: &Logical) -> Self::R;
+    fn visit_set (&mut self, set: &Set) -> Self::R;
+    fn visit_super (&mut self, super_: &Super) -> Self::R;
+    fn visit_this (&mut self, this: &This) -> Self::R;
     fn visit_unary (&mut self, unary: &Unary) -> Self::R;
     fn visit_variable (&mut self, variable: &Variable) -> Self::R;
 }
@@ -35,6 +79,8 @@ pub trait Visitor {
 pub struct Assign {
     pub name: Token,
     pub value: Box<Expr>,
+    pub slot: Cell<Option<Slot>>,
+    pub span: Span,
 }
 
 impl Assign {
@@ -47,6 +93,7 @@ pub struct Binary {
     pub left: Box<Expr>,
     pub operator: Token,
     pub right: Box<Expr>,
+    pub span: Span,
 }
 
 impl Binary {
@@ -55,8 +102,34 @@ impl Binary {
     }
 }
 
+pub struct Call {
+    pub callee: Box<Expr>,
+    pub paren: Token,
+    pub arguments: Vec<Box<Expr>>,
+    pub span: Span,
+}
+
+impl Call {
+    pub fn accept<T: Visitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_call(self)
+    }
+}
+
+pub struct Get {
+    pub object: Box<Expr>,
+    pub name: Token,
+    pub span: Span,
+}
+
+impl Get {
+    pub fn accept<T: Visitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_get(self)
+    }
+}
+
 pub struct Grouping {
     pub expression: Box<Expr>,
+    pub span: Span,
 }
 
 impl Grouping {
@@ -65,8 +138,20 @@ impl Grouping {
     }
 }
 
+pub struct Interpolation {
+    pub parts: Vec<Box<Expr>>,
+    pub span: Span,
+}
+
+impl Interpolation {
+    pub fn accept<T: Visitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_interpolation(self)
+    }
+}
+
 pub struct Literal {
     pub value: Token,
+    pub span: Span,
 }
 
 impl Literal {
@@ -75,9 +160,61 @@ impl Literal {
     }
 }
 
+pub struct Logical {
+    pub left: Box<Expr>,
+    pub operator: Token,
+    pub right: Box<Expr>,
+    pub span: Span,
+}
+
+impl Logical {
+    pub fn accept<T: Visitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_logical(self)
+    }
+}
+
+pub struct Set {
+    pub object: Box<Expr>,
+    pub name: Token,
+    pub value: Box<Expr>,
+    pub span: Span,
+}
+
+impl Set {
+    pub fn accept<T: Visitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_set(self)
+    }
+}
+
+pub struct Super {
+    pub keyword: Token,
+    pub method: Token,
+    pub slot: Cell<Option<Slot>>,
+    pub span: Span,
+}
+
+impl Super {
+    pub fn accept<T: Visitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_super(self)
+    }
+}
+
+pub struct This {
+    pub keyword: Token,
+    pub slot: Cell<Option<Slot>>,
+    pub span: Span,
+}
+
+impl This {
+    pub fn accept<T: Visitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_this(self)
+    }
+}
+
 pub struct Unary {
     pub operator: Token,
     pub right: Box<Expr>,
+    pub span: Span,
 }
 
 impl Unary {
@@ -88,6 +225,8 @@ impl Unary {
 
 pub struct Variable {
     pub name: Token,
+    pub slot: Cell<Option<Slot>>,
+    pub span: Span,
 }
 
 impl Variable {
@@ -100,6 +239,14 @@ pub enum Stmt {
     Expression(Expression),
     Print(Print),
     Var(Var),
+    Block(Block),
+    If(If),
+    While(While),
+    Break(Break),
+    Continue(Continue),
+    Function(Function),
+    Return(Return),
+    Class(Class),
 }
 
 impl Stmt {
@@ -108,6 +255,30 @@ impl Stmt {
             Stmt::Expression(expression) => visitor.visit_expression(expression),
             Stmt::Print(print) => visitor.visit_print(print),
             Stmt::Var(var) => visitor.visit_var(var),
+            Stmt::Block(block) => visitor.visit_block(block),
+            Stmt::If(if_) => visitor.visit_if(if_),
+            Stmt::While(while_) => visitor.visit_while(while_),
+            Stmt::Break(break_) => visitor.visit_break(break_),
+            Stmt::Continue(continue_) => visitor.visit_continue(continue_),
+            Stmt::Function(function) => visitor.visit_function(function),
+            Stmt::Return(return_) => visitor.visit_return(return_),
+            Stmt::Class(class) => visitor.visit_class(class),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expression(expression) => expression.span,
+            Stmt::Print(print) => print.span,
+            Stmt::Var(var) => var.span,
+            Stmt::Block(block) => block.span,
+            Stmt::If(if_) => if_.span,
+            Stmt::While(while_) => while_.span,
+            Stmt::Break(break_) => break_.span,
+            Stmt::Continue(continue_) => continue_.span,
+            Stmt::Function(function) => function.span,
+            Stmt::Return(return_) => return_.span,
+            Stmt::Class(class) => class.span,
         }
     }
 }
@@ -117,10 +288,19 @@ pub trait StmtVisitor {
     fn visit_expression (&mut self, expression: &Expression) -> Self::R;
     fn visit_print (&mut self, print: &Print) -> Self::R;
     fn visit_var (&mut self, var: &Var) -> Self::R;
+    fn visit_block (&mut self, block: &Block) -> Self::R;
+    fn visit_if (&mut self, if_: &If) -> Self::R;
+    fn visit_while (&mut self, while_: &While) -> Self::R;
+    fn visit_break (&mut self, break_: &Break) -> Self::R;
+    fn visit_continue (&mut self, continue_: &Continue) -> Self::R;
+    fn visit_function (&mut self, function: &Function) -> Self::R;
+    fn visit_return (&mut self, return_: &Return) -> Self::R;
+    fn visit_class (&mut self, class: &Class) -> Self::R;
 }
 
 pub struct Expression {
     pub expression: Box<Expr>,
+    pub span: Span,
 }
 
 impl Expression {
@@ -131,6 +311,7 @@ impl Expression {
 
 pub struct Print {
     pub expression: Box<Expr>,
+    pub span: Span,
 }
 
 impl Print {
@@ -142,6 +323,7 @@ impl Print {
 pub struct Var {
     pub name: Token,
     pub initializer: Option<Box<Expr>>,
+    pub span: Span,
 }
 
 impl Var {
@@ -150,3 +332,100 @@ impl Var {
     }
 }
 
+pub struct Block {
+    pub statements: Vec<Stmt>,
+    pub span: Span,
+}
+
+impl Block {
+    pub fn accept<T: StmtVisitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_block(self)
+    }
+}
+
+pub struct If {
+    pub condition: Box<Expr>,
+    pub then_branch: Box<Stmt>,
+    pub else_branch: Option<Box<Stmt>>,
+    pub span: Span,
+}
+
+impl If {
+    pub fn accept<T: StmtVisitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_if(self)
+    }
+}
+
+pub struct While {
+    pub condition: Box<Expr>,
+    pub body: Box<Stmt>,
+    pub increment: Option<Box<Expr>>,
+    pub span: Span,
+}
+
+impl While {
+    pub fn accept<T: StmtVisitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_while(self)
+    }
+}
+
+pub struct Break {
+    pub keyword: Token,
+    pub span: Span,
+}
+
+impl Break {
+    pub fn accept<T: StmtVisitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_break(self)
+    }
+}
+
+pub struct Continue {
+    pub keyword: Token,
+    pub span: Span,
+}
+
+impl Continue {
+    pub fn accept<T: StmtVisitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_continue(self)
+    }
+}
+
+pub struct Function {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Rc<Vec<Stmt>>,
+    pub span: Span,
+}
+
+impl Function {
+    pub fn accept<T: StmtVisitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_function(self)
+    }
+}
+
+pub struct Return {
+    pub keyword: Token,
+    pub value: Option<Box<Expr>>,
+    pub span: Span,
+}
+
+impl Return {
+    pub fn accept<T: StmtVisitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_return(self)
+    }
+}
+
+pub struct Class {
+    pub name: Token,
+    pub superclass: Option<Box<Expr>>,
+    pub methods: Vec<Function>,
+    pub span: Span,
+}
+
+impl Class {
+    pub fn accept<T: StmtVisitor> (&mut self, visitor: &mut T) -> T::R {
+        visitor.visit_class(self)
+    }
+}
+
@@ -0,0 +1,8 @@
+// location of a local variable as computed by the resolver, `depth` is the
+// number of scopes between the use and the declaration and `index` is the
+// position of the variable among the declarations of that scope
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
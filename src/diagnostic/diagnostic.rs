@@ -0,0 +1,187 @@
+use crate::token::span::Span;
+use crate::token::token::Token;
+use crate::token::token_type::TokenType;
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// phase that reported the diagnostic, stable so callers can match on it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Code {
+    Scan,
+    Parse,
+    Resolve,
+    Compile,
+    Runtime,
+}
+
+// what the message is reported at, kept to render "at 'x'" and "at end"
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    Line,
+    Token(String),
+    End,
+}
+
+// secondary span pointing at related code, e.g. a previous declaration
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    pub message: String,
+    pub span: Span,
+    pub location: Location,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    // the source ended before what's reported was finished, e.g. a string
+    // that isn't closed, so typing more could fix it
+    pub incomplete: bool,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, span: Span, message: String) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message,
+            span,
+            location: Location::Line,
+            labels: vec![],
+            notes: vec![],
+            incomplete: false,
+        }
+    }
+
+    // for phases that only know the line, such as the bytecode compiler
+    pub fn error_at_line(code: Code, line: u32, message: String) -> Self {
+        Diagnostic::error(code, Span { line, ..Span::default() }, message)
+    }
+
+    pub fn error_at(code: Code, token: &Token, message: String) -> Self {
+        let location = match token.token_type {
+            TokenType::EOF => Location::End,
+            _ => Location::Token(token.lexeme.clone()),
+        };
+
+        Diagnostic {
+            incomplete: location == Location::End,
+            location,
+            ..Diagnostic::error(code, token.span(), message)
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: &str) -> Self {
+        self.labels.push(Label { span, message: message.to_string() });
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    pub fn incomplete(mut self) -> Self {
+        self.incomplete = true;
+        self
+    }
+}
+
+// same format as the reference lox, runtime errors have no location
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.code == Code::Runtime {
+            return write!(f, "[line {}]: {}", self.span.line, self.message);
+        }
+
+        let severity = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        };
+        let location = match &self.location {
+            Location::Line => String::new(),
+            Location::Token(lexeme) => format!(" at '{}'", lexeme),
+            Location::End => String::from(" at end"),
+        };
+        write!(f, "[line {}] {}{}: {}",
+               self.span.line, severity, location, self.message)
+    }
+}
+
+// called with every diagnostic as soon as it is reported
+pub type Emitter = Box<dyn FnMut(&Diagnostic)>;
+
+// Sink every phase reports its diagnostics to. They are kept so callers can
+// inspect them once a phase is done, and handed to the emitter, if any, as
+// soon as they are reported so they interleave with the program output.
+#[derive(Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+    emitter: Option<Emitter>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Diagnostics::default()
+    }
+
+    pub fn with_emitter(emitter: impl FnMut(&Diagnostic) + 'static) -> Self {
+        Diagnostics { diagnostics: vec![], emitter: Some(Box::new(emitter)) }
+    }
+
+    // replaces the emitter, e.g. to render against the source being run
+    pub fn set_emitter(&mut self, emitter: impl FnMut(&Diagnostic) + 'static) {
+        self.emitter = Some(Box::new(emitter));
+    }
+
+    pub fn report(&mut self, diagnostic: Diagnostic) {
+        if let Some(emitter) = &mut self.emitter {
+            emitter(&diagnostic);
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    // errors found before running, the code shouldn't be run
+    pub fn has_compile_errors(&self) -> bool {
+        self.diagnostics.iter().any(|diagnostic| {
+            diagnostic.severity == Severity::Error
+                && diagnostic.code != Code::Runtime
+        })
+    }
+
+    pub fn has_runtime_errors(&self) -> bool {
+        self.diagnostics.iter()
+            .any(|diagnostic| diagnostic.code == Code::Runtime)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    // removes every diagnostic reported so far, the emitter is kept
+    pub fn take(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    pub fn clear(&mut self) {
+        self.diagnostics.clear();
+    }
+}
@@ -0,0 +1,153 @@
+use crate::token::span::Span;
+
+use super::diagnostic::{Code, Diagnostic, Severity};
+
+// Encodes a diagnostic as a single line JSON object for tools that consume
+// the errors, e.g.
+//
+//   {"phase":"parse","severity":"error","message":"Expect ';' after value.",
+//    "file":"test.lox","line":1,"column":8,"span":{"start":7,"end":8},
+//    "labels":[],"notes":[]}
+//
+// `column` and `span` are null when only the line is known, as for errors
+// from the virtual machine.
+pub fn to_json(diagnostic: &Diagnostic, file: &str) -> String {
+    let phase = match diagnostic.code {
+        Code::Scan => "scan",
+        Code::Parse => "parse",
+        Code::Resolve => "resolve",
+        Code::Compile => "compile",
+        Code::Runtime => "runtime",
+    };
+    let severity = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+
+    let labels: Vec<String> = diagnostic.labels.iter()
+        .map(|label| format!("{{\"message\":{},{}}}",
+                             string(&label.message), position(label.span)))
+        .collect();
+    let notes: Vec<String> = diagnostic.notes.iter()
+        .map(|note| string(note))
+        .collect();
+
+    format!(
+        "{{\"phase\":\"{}\",\"severity\":\"{}\",\"message\":{},\
+         \"file\":{},{},\"labels\":[{}],\"notes\":[{}]}}",
+        phase,
+        severity,
+        string(&diagnostic.message),
+        string(file),
+        position(diagnostic.span),
+        labels.join(","),
+        notes.join(","),
+    )
+}
+
+// the "line", "column" and "span" members
+fn position(span: Span) -> String {
+    if span.column == 0 {
+        return format!("\"line\":{},\"column\":null,\"span\":null", span.line);
+    }
+
+    format!("\"line\":{},\"column\":{},\"span\":{{\"start\":{},\"end\":{}}}",
+            span.line, span.column, span.start, span.end)
+}
+
+fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                out.push_str(&format!("\\u{:04x}", c as u32))
+            },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: usize, end: usize, line: u32, column: u32) -> Span {
+        Span { start, end, line, column }
+    }
+
+    #[test]
+    fn encodes_every_member() {
+        let diagnostic = Diagnostic::error(
+            Code::Parse, span(7, 8, 1, 8),
+            String::from("Expect ';' after value."),
+        );
+
+        assert_eq!(to_json(&diagnostic, "test.lox"),
+                   "{\"phase\":\"parse\",\"severity\":\"error\",\
+                    \"message\":\"Expect ';' after value.\",\
+                    \"file\":\"test.lox\",\"line\":1,\"column\":8,\
+                    \"span\":{\"start\":7,\"end\":8},\
+                    \"labels\":[],\"notes\":[]}");
+    }
+
+    #[test]
+    fn line_only_errors_have_null_positions() {
+        let diagnostic = Diagnostic::error_at_line(
+            Code::Runtime, 3, String::from("Stack overflow."),
+        );
+
+        assert_eq!(to_json(&diagnostic, "vm.lox"),
+                   "{\"phase\":\"runtime\",\"severity\":\"error\",\
+                    \"message\":\"Stack overflow.\",\"file\":\"vm.lox\",\
+                    \"line\":3,\"column\":null,\"span\":null,\
+                    \"labels\":[],\"notes\":[]}");
+    }
+
+    #[test]
+    fn encodes_labels_and_notes() {
+        let diagnostic = Diagnostic::error(
+            Code::Resolve, span(17, 18, 1, 18),
+            String::from("Already a variable with this name in this scope."),
+        )
+        .with_label(span(6, 7, 1, 7), "previous declaration here")
+        .with_note("shadowing needs a new block");
+
+        let json = to_json(&diagnostic, "scope.lox");
+        assert!(json.ends_with(
+            "\"labels\":[{\"message\":\"previous declaration here\",\
+             \"line\":1,\"column\":7,\"span\":{\"start\":6,\"end\":7}}],\
+             \"notes\":[\"shadowing needs a new block\"]}"
+        ), "{}", json);
+    }
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(string("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(string("C:\\lox"), "\"C:\\\\lox\"");
+        assert_eq!(string("a\nb\r\tc"), "\"a\\nb\\r\\tc\"");
+        assert_eq!(string("\u{0}\u{1b}"), "\"\\u0000\\u001b\"");
+        assert_eq!(string("日本 ☃"), "\"日本 ☃\"");
+    }
+
+    #[test]
+    fn escapes_the_file_and_message() {
+        let diagnostic = Diagnostic::error(
+            Code::Scan, span(6, 8, 1, 7),
+            String::from("Invalid escape sequence '\\q'."),
+        );
+
+        let json = to_json(&diagnostic, "dir\\\"odd\".lox");
+        assert!(json.contains(
+            "\"message\":\"Invalid escape sequence '\\\\q'.\",\
+             \"file\":\"dir\\\\\\\"odd\\\".lox\""
+        ), "{}", json);
+    }
+}
@@ -0,0 +1,380 @@
+use crate::token::span::Span;
+
+use super::diagnostic::{Code, Diagnostic, Severity};
+
+use unicode_width::UnicodeWidthStr;
+
+use std::io::{self, IsTerminal};
+
+// value of the `--color` option
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn parse(choice: &str) -> Option<ColorChoice> {
+        match choice {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    // diagnostics go to stderr, `auto` only colors it when it's a terminal
+    pub fn use_color(self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stderr().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+
+// something to underline in the snippet, the primary span gets carets and
+// labels get dashes
+struct Annotation<'a> {
+    span: Span,
+    mark: char,
+    message: Option<&'a str>,
+    color: &'static str,
+}
+
+// Renders diagnostics rustc-style, with the offending source lines and the
+// spans underlined:
+//
+//   error: Expect ';' after value.
+//    --> test.lox:1:8
+//     |
+//   1 | print 1
+//     |        ^
+pub struct Renderer {
+    file: String,
+    source: String,
+    color: bool,
+}
+
+impl Renderer {
+    pub fn new(file: &str, source: &str, color: bool) -> Self {
+        Renderer {
+            file: file.to_string(),
+            source: source.to_string(),
+            color,
+        }
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let (level, color) = match (diagnostic.severity, diagnostic.code) {
+            (Severity::Warning, _) => ("warning", YELLOW),
+            (Severity::Error, Code::Runtime) => ("runtime error", RED),
+            (Severity::Error, _) => ("error", RED),
+        };
+
+        let mut annotations = vec![Annotation {
+            span: diagnostic.span,
+            mark: '^',
+            message: None,
+            color,
+        }];
+        for label in &diagnostic.labels {
+            annotations.push(Annotation {
+                span: label.span,
+                mark: '-',
+                message: Some(&label.message),
+                color: BLUE,
+            });
+        }
+        annotations.sort_by_key(|annotation| annotation.span.line);
+
+        let gutter = annotations.iter()
+            .map(|annotation| annotation.span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let blank = " ".repeat(gutter);
+
+        let mut out = format!(
+            "{}{}\n",
+            self.paint(&format!("{}{}", BOLD, color), level),
+            self.paint(BOLD, &format!(": {}", diagnostic.message)),
+        );
+
+        let span = diagnostic.span;
+        let position = if span.column == 0 {
+            format!("{}:{}", self.file, span.line)
+        } else {
+            format!("{}:{}:{}", self.file, span.line, span.column)
+        };
+        out += &format!("{}{} {}\n",
+                        blank, self.paint_gutter("-->"), position);
+        out += &format!("{} {}\n", blank, self.paint_gutter("|"));
+
+        let mut previous_line = None;
+        for annotation in &annotations {
+            let line = annotation.span.line;
+            if previous_line != Some(line) {
+                if previous_line.is_some_and(|previous| line > previous + 1) {
+                    out += &format!("{}\n", self.paint_gutter("..."));
+                }
+                out += &format!(
+                    "{} {}\n",
+                    self.paint_gutter(&format!("{:>width$} |", line,
+                                               width = gutter)),
+                    expand_tabs(self.line_text(line)),
+                );
+                previous_line = Some(line);
+            }
+
+            if let Some(underline) = self.underline(annotation) {
+                out += &format!("{} {} {}\n",
+                                blank, self.paint_gutter("|"), underline);
+            }
+        }
+
+        for note in &diagnostic.notes {
+            out += &format!("{} {} {}\n",
+                            blank,
+                            self.paint_gutter("="),
+                            format_args!("{}: {}", self.paint(BOLD, "note"),
+                                         note));
+        }
+
+        // the caller adds the final newline
+        out.pop();
+        out
+    }
+
+    fn line_text(&self, line: u32) -> &str {
+        self.source.lines()
+            .nth((line as usize).saturating_sub(1))
+            .unwrap_or("")
+    }
+
+    // marks under the span, from its start to the end of its first line.
+    // Wide characters, e.g. CJK, take two columns in a terminal.
+    fn underline(&self, annotation: &Annotation) -> Option<String> {
+        let span = annotation.span;
+        if span.column == 0 {
+            return None;
+        }
+
+        let text = self.line_text(span.line);
+        let prefix: String = text.chars()
+            .take(span.column as usize - 1)
+            .collect();
+        let spanned = self.source.get(span.start..span.end).unwrap_or("");
+        let width = spanned.lines().next()
+            .map_or(0, |first| expand_tabs(first).width())
+            .max(1);
+
+        let mut underline = format!(
+            "{}{}",
+            " ".repeat(expand_tabs(&prefix).width()),
+            self.paint(&format!("{}{}", BOLD, annotation.color),
+                       &annotation.mark.to_string().repeat(width)),
+        );
+        if let Some(message) = annotation.message {
+            underline += &format!(
+                " {}",
+                self.paint(&format!("{}{}", BOLD, annotation.color), message)
+            );
+        }
+        Some(underline)
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn paint_gutter(&self, text: &str) -> String {
+        self.paint(&format!("{}{}", BOLD, BLUE), text)
+    }
+}
+
+// tabs would make the marks drift from the text they point at
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', "    ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // span of the first occurrence of `needle` in `source`
+    fn span_of(source: &str, needle: &str) -> Span {
+        let start = source.find(needle).expect("needle in source");
+        let before = &source[..start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Span {
+            start,
+            end: start + needle.len(),
+            line: before.matches('\n').count() as u32 + 1,
+            column: before[line_start..].chars().count() as u32 + 1,
+        }
+    }
+
+    fn render(source: &str, diagnostic: &Diagnostic) -> String {
+        Renderer::new("test.lox", source, false).render(diagnostic)
+    }
+
+    #[test]
+    fn underlines_the_span() {
+        let source = "var a = 1;\nprint a +;\n";
+        let diagnostic = Diagnostic::error(Code::Parse, span_of(source, "+"),
+                                           String::from("Expect expression."));
+
+        assert_eq!(render(source, &diagnostic), "\
+error: Expect expression.
+ --> test.lox:2:9
+  |
+2 | print a +;
+  |         ^");
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        let source = "print \"日本\" + nope;";
+        let diagnostic = Diagnostic::error(
+            Code::Runtime, span_of(source, "nope"),
+            String::from("Undefined variable 'nope'."),
+        );
+
+        assert_eq!(render(source, &diagnostic), "\
+runtime error: Undefined variable 'nope'.
+ --> test.lox:1:14
+  |
+1 | print \"日本\" + nope;
+  |                ^^^^");
+    }
+
+    #[test]
+    fn tabs_are_expanded_in_the_line_and_the_underline() {
+        let source = "\tprint\tnope;";
+        let diagnostic = Diagnostic::error(
+            Code::Runtime, span_of(source, "nope"),
+            String::from("Undefined variable 'nope'."),
+        );
+
+        assert_eq!(render(source, &diagnostic), "\
+runtime error: Undefined variable 'nope'.
+ --> test.lox:1:8
+  |
+1 |     print    nope;
+  |              ^^^^");
+    }
+
+    #[test]
+    fn underlines_only_the_first_line_of_a_span() {
+        let source = "print \"one\ntwo\";";
+        let diagnostic = Diagnostic::error(
+            Code::Parse, span_of(source, "\"one\ntwo\""),
+            String::from("Bad."),
+        );
+
+        assert_eq!(render(source, &diagnostic), "\
+error: Bad.
+ --> test.lox:1:7
+  |
+1 | print \"one
+  |       ^^^^");
+    }
+
+    #[test]
+    fn labels_are_sorted_by_line_and_the_gutter_fits_them() {
+        let mut source = String::from("var a = 1;\n");
+        source += &"\n".repeat(8);
+        source += "var a = 2;\n";
+        // the declaration is reported after the one it conflicts with
+        let diagnostic = Diagnostic::error(
+            Code::Resolve,
+            span_of(&source, "a = 2"),
+            String::from("Already a variable with this name in this scope."),
+        )
+        .with_label(span_of(&source, "a = 1"), "previous declaration here");
+
+        assert_eq!(render(&source, &diagnostic), "\
+error: Already a variable with this name in this scope.
+  --> test.lox:10:5
+   |
+ 1 | var a = 1;
+   |     ----- previous declaration here
+...
+10 | var a = 2;
+   |     ^^^^^");
+    }
+
+    #[test]
+    fn labels_on_the_same_line_share_it() {
+        let source = "a = b;";
+        let diagnostic = Diagnostic::error(Code::Resolve, span_of(source, "b"),
+                                           String::from("Bad."))
+            .with_label(span_of(source, "a"), "here");
+
+        assert_eq!(render(source, &diagnostic), "\
+error: Bad.
+ --> test.lox:1:5
+  |
+1 | a = b;
+  |     ^
+  | - here");
+    }
+
+    #[test]
+    fn notes_follow_the_snippet() {
+        let source = "/* open";
+        let diagnostic = Diagnostic::error(
+            Code::Scan, span_of(source, "/*"),
+            String::from("Unterminated block comment."),
+        )
+        .with_note("block comments nest");
+
+        assert_eq!(render(source, &diagnostic), "\
+error: Unterminated block comment.
+ --> test.lox:1:1
+  |
+1 | /* open
+  | ^^
+  = note: block comments nest");
+    }
+
+    #[test]
+    fn line_only_errors_have_no_underline() {
+        let source = "print 1;\nfun f() {}\n";
+        let diagnostic = Diagnostic::error_at_line(
+            Code::Runtime, 2, String::from("Stack overflow."),
+        );
+
+        assert_eq!(render(source, &diagnostic), "\
+runtime error: Stack overflow.
+ --> test.lox:2
+  |
+2 | fun f() {}");
+    }
+
+    #[test]
+    fn colors_only_when_asked() {
+        let source = "nope;";
+        let diagnostic = Diagnostic::error(
+            Code::Runtime, span_of(source, "nope"),
+            String::from("Undefined variable 'nope'."),
+        );
+
+        let colored = Renderer::new("test.lox", source, true)
+            .render(&diagnostic);
+        assert!(colored.starts_with("\x1b[1m\x1b[31mruntime error\x1b[0m"));
+        assert!(colored.contains("\x1b[1m\x1b[31m^^^^\x1b[0m"));
+        assert!(!render(source, &diagnostic).contains('\x1b'));
+    }
+}
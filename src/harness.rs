@@ -0,0 +1,2 @@
+pub mod expectation;
+pub mod runner;
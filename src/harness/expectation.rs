@@ -0,0 +1,153 @@
+// Expectations of a test script, written as comments in the style of the
+// Crafting Interpreters test suite:
+//
+//   print 1 + 2;    // expect: 3
+//   print a;        // expect runtime error: Undefined variable 'a'.
+//   var = 1;        // Error at '=': Expect variable name.
+//   // [line 7] Error at end: Expect '}' after block.
+//
+// Errors can be restricted to one backend with `[java line N]` for the
+// tree-walking interpreter or `[c line N]` for the virtual machine.
+#[derive(Debug, Default, PartialEq)]
+pub struct Expectations {
+    // (line of the comment, printed line)
+    pub output: Vec<(u32, String)>,
+    // compile errors as reported by `--error-format=short`
+    pub errors: Vec<String>,
+    // (line, message)
+    pub runtime_error: Option<(u32, String)>,
+}
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+
+impl Expectations {
+    // `dialect` is "java" or "c", errors for the other one are skipped
+    pub fn parse(source: &str, dialect: &str) -> Self {
+        let mut expectations = Expectations::default();
+
+        for (index, text) in source.lines().enumerate() {
+            let line = index as u32 + 1;
+
+            if let Some(start) = text.find(EXPECT) {
+                let output = &text[start + EXPECT.len()..];
+                expectations.output.push((line, output.to_string()));
+            } else if let Some(start) = text.find(EXPECT_RUNTIME_ERROR) {
+                let message = &text[start + EXPECT_RUNTIME_ERROR.len()..];
+                expectations.runtime_error = Some((line, message.to_string()));
+            } else if let Some(error) = parse_error(text, line, dialect) {
+                expectations.errors.push(error);
+            }
+        }
+
+        expectations
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if !self.errors.is_empty() {
+            65
+        } else if self.runtime_error.is_some() {
+            70
+        } else {
+            0
+        }
+    }
+}
+
+// `// Error...` or `// [line N] Error...`, returned as `[line N] Error...`
+fn parse_error(text: &str, line: u32, dialect: &str) -> Option<String> {
+    let comment = &text[text.find("// ")? + 3..];
+
+    if comment.starts_with("Error") {
+        return Some(format!("[line {}] {}", line, comment));
+    }
+
+    let rest = comment.strip_prefix('[')?;
+    let (prefix, rest) = rest.split_at(rest.find(']')?);
+    let error = rest[1..].trim_start();
+    if !error.starts_with("Error") {
+        return None;
+    }
+
+    let mut words = prefix.split_whitespace();
+    let mut word = words.next()?;
+    if word != "line" {
+        if word != dialect {
+            return None;
+        }
+        word = words.next()?;
+    }
+    if word != "line" {
+        return None;
+    }
+    let line: u32 = words.next()?.parse().ok()?;
+
+    Some(format!("[line {}] {}", line, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_and_runtime_errors_are_expected_on_their_line() {
+        let source = "print 1;   // expect: 1\n\
+                      print \"a\"; // expect: a\n\
+                      print a;   // expect runtime error: Undefined variable.";
+        let expectations = Expectations::parse(source, "java");
+
+        assert_eq!(expectations.output,
+                   vec![(1, String::from("1")), (2, String::from("a"))]);
+        assert_eq!(expectations.runtime_error,
+                   Some((3, String::from("Undefined variable."))));
+        assert!(expectations.errors.is_empty());
+        assert_eq!(expectations.exit_code(), 70);
+    }
+
+    #[test]
+    fn bare_errors_are_on_the_line_of_the_comment() {
+        assert_eq!(parse_error("var = 1; // Error at '=': Expect name.", 4,
+                               "java"),
+                   Some(String::from("[line 4] Error at '=': Expect name.")));
+    }
+
+    #[test]
+    fn errors_can_give_their_line() {
+        assert_eq!(parse_error("// [line 7] Error at end: Expect '}'.", 1,
+                               "c"),
+                   Some(String::from("[line 7] Error at end: Expect '}'.")));
+    }
+
+    #[test]
+    fn errors_of_the_other_backend_are_skipped() {
+        let source = "// [java line 2] Error at 'a': Tree-walker.\n\
+                      // [c line 3] Error at 'a': Virtual machine.";
+
+        assert_eq!(Expectations::parse(source, "java").errors,
+                   vec!["[line 2] Error at 'a': Tree-walker."]);
+        assert_eq!(Expectations::parse(source, "c").errors,
+                   vec!["[line 3] Error at 'a': Virtual machine."]);
+    }
+
+    #[test]
+    fn other_comments_expect_nothing() {
+        for text in ["print 1; // just a comment",
+                     "// [line 2] not an error",
+                     "// [line two] Error: Not a line number.",
+                     "// [java] Error: No line.",
+                     "// [lua line 2] Error: Unknown backend."] {
+            assert_eq!(parse_error(text, 1, "java"), None, "{}", text);
+        }
+
+        let expectations = Expectations::parse("print 1;\n// note", "java");
+        assert_eq!(expectations, Expectations::default());
+        assert_eq!(expectations.exit_code(), 0);
+    }
+
+    #[test]
+    fn compile_errors_take_precedence_in_the_exit_code() {
+        let source = "a; // Error at 'a': Compile.\n\
+                      b; // expect runtime error: Runtime.";
+        assert_eq!(Expectations::parse(source, "java").exit_code(), 65);
+    }
+}
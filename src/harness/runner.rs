@@ -0,0 +1,144 @@
+use super::expectation::Expectations;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// result of running one script
+pub struct Outcome {
+    pub path: PathBuf,
+    pub failures: Vec<String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+// Runs every `.lox` script under a directory through an interpreter binary
+// and checks what it prints against the annotations in the script. Each
+// script gets its own process so that crashes and exit codes can be checked.
+pub struct Runner {
+    program: PathBuf,
+    args: Vec<String>,
+    dialect: &'static str,
+}
+
+impl Runner {
+    // `args` are passed before the script, `vm` selects the errors that are
+    // specific to the virtual machine
+    pub fn new(program: PathBuf, mut args: Vec<String>, vm: bool) -> Self {
+        if vm {
+            args.insert(0, String::from("--vm"));
+        }
+        args.push(String::from("--color=never"));
+        args.push(String::from("--error-format=short"));
+
+        Runner {
+            program,
+            args,
+            dialect: if vm { "c" } else { "java" },
+        }
+    }
+
+    pub fn run_dir(&self, dir: &Path) -> io::Result<Vec<Outcome>> {
+        let mut paths = vec![];
+        collect_scripts(dir, &mut paths)?;
+        paths.sort();
+
+        paths.into_iter().map(|path| self.run_file(path)).collect()
+    }
+
+    pub fn run_file(&self, path: PathBuf) -> io::Result<Outcome> {
+        let source = fs::read_to_string(&path)?;
+        let expected = Expectations::parse(&source, self.dialect);
+
+        let output = Command::new(&self.program)
+            .args(&self.args)
+            .arg(&path)
+            .output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        let mut failures = vec![];
+        check_errors(&expected, stderr.lines().collect(), &mut failures);
+        check_output(&expected, stdout.lines().collect(), &mut failures);
+
+        match output.status.code() {
+            Some(code) if code == expected.exit_code() => {},
+            Some(code) => failures.push(format!(
+                "Expected exit code {} and got {}.",
+                expected.exit_code(), code,
+            )),
+            None => failures.push(String::from("Killed by a signal.")),
+        }
+
+        Ok(Outcome { path, failures })
+    }
+}
+
+fn collect_scripts(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_scripts(&path, paths)?;
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn check_errors(expected: &Expectations, stderr: Vec<&str>,
+                failures: &mut Vec<String>) {
+    if let Some((line, message)) = &expected.runtime_error {
+        let error = format!("[line {}]: {}", line, message);
+        if !stderr.contains(&error.as_str()) {
+            failures.push(format!(
+                "Expected runtime error '{}' and got:", error,
+            ));
+            failures.extend(stderr.iter().map(|line| format!("  {}", line)));
+        }
+        return;
+    }
+
+    // compile errors must match one for one and in order
+    let mut stderr = stderr.into_iter();
+    for error in &expected.errors {
+        match stderr.next() {
+            Some(actual) if actual == error => {},
+            Some(actual) => failures.push(format!(
+                "Expected error '{}' and got '{}'.", error, actual,
+            )),
+            None => failures.push(format!("Missing expected error '{}'.",
+                                          error)),
+        }
+    }
+    for actual in stderr {
+        failures.push(format!("Unexpected error '{}'.", actual));
+    }
+}
+
+// annotations can't hold trailing whitespace, so it's ignored in the output
+fn check_output(expected: &Expectations, stdout: Vec<&str>,
+                failures: &mut Vec<String>) {
+    let mut stdout = stdout.into_iter();
+    for (line, output) in &expected.output {
+        match stdout.next() {
+            Some(actual) if actual.trim_end() == output => {},
+            Some(actual) => failures.push(format!(
+                "Expected output '{}' on line {} and got '{}'.",
+                output, line, actual.trim_end(),
+            )),
+            None => failures.push(format!(
+                "Missing expected output '{}' on line {}.", output, line,
+            )),
+        }
+    }
+    for actual in stdout {
+        failures.push(format!("Got output '{}' when none was expected.",
+                              actual.trim_end()));
+    }
+}
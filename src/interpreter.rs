@@ -1,3 +1,8 @@
+pub mod callable;
+pub mod collector;
 pub mod environment;
 pub mod interpreter;
+pub mod native;
+pub mod output;
+pub mod resolver;
 
@@ -0,0 +1,157 @@
+use crate::ast::node::Stmt;
+use crate::token::token::Token;
+use crate::token::value::Value;
+
+use super::collector::Collector;
+use super::environment::Environment;
+use super::interpreter::{Interpreter, InterpreterError};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+// runtime representation of a `fun` declaration, the body is shared with the
+// AST so creating a closure doesn't copy it
+pub struct Function {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Rc<Vec<Stmt>>,
+    // environment active where the function was declared
+    pub closure: Rc<RefCell<Environment>>,
+    // `init` methods always return `this`
+    pub is_initializer: bool,
+}
+
+impl Function {
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    // wraps the closure in a scope where `this` refers to `instance`
+    pub fn bind(&self,
+                instance: Rc<Instance>,
+                collector: &mut Collector) -> Function {
+        let mut environment =
+            Environment::from_enclosing(Rc::clone(&self.closure));
+        environment.define_var(String::from("this"), Value::Instance(instance));
+
+        Function {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            body: Rc::clone(&self.body),
+            closure: collector.environment(environment),
+            is_initializer: self.is_initializer,
+        }
+    }
+}
+
+// functions are compared by identity, two declarations with the same body
+// are still different functions
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.name.lexeme)
+    }
+}
+
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<Rc<Class>>,
+    pub methods: HashMap<String, Rc<Function>>,
+}
+
+impl Class {
+    // looks the method up on this class and then along the superclass chain
+    pub fn find_method(&self, name: &str) -> Option<Rc<Function>> {
+        match self.methods.get(name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+
+    // calling a class runs its initializer, so it takes the same arguments
+    pub fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |init| init.arity())
+    }
+}
+
+impl PartialEq for Class {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: RefCell<HashMap<String, Value>>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Instance { class, fields: RefCell::new(HashMap::new()) }
+    }
+
+    // fields shadow methods, methods are bound to this instance on access
+    pub fn get(instance: &Rc<Instance>,
+               name: &Token,
+               collector: &mut Collector) -> Option<Value> {
+        if let Some(value) = instance.fields.borrow().get(&name.lexeme) {
+            return Some(value.clone());
+        }
+
+        let method = instance.class.find_method(&name.lexeme)?;
+        let method = method.bind(Rc::clone(instance), collector);
+        Some(Value::Function(collector.function(method)))
+    }
+
+    pub fn set(&self, name: &Token, value: Value) {
+        self.fields.borrow_mut().insert(name.lexeme.clone(), value);
+    }
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+// signature of the Rust functions callable from Lox, the arguments have
+// already been checked against the arity
+pub type NativeFn =
+    fn(&mut Interpreter, &[Value]) -> Result<Value, InterpreterError>;
+
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    pub function: NativeFn,
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
@@ -0,0 +1,410 @@
+use crate::token::value::Value;
+use crate::vm::heap::GcConfig;
+
+use super::callable::{Class, Function, Instance};
+use super::environment::Environment;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::{Rc, Weak};
+
+// Cycle collector of the tree-walker. Its objects are reference counted,
+// which frees everything but cycles such as a closure stored in the scope
+// it closes over. Every environment, function, class and instance is
+// allocated here, and a collection looks for the ones only kept alive by
+// other allocated objects: an object with more strong references than the
+// allocated objects hold to it is referenced from Rust, by the interpreter
+// or by a host, and is a root.
+pub struct Collector {
+    objects: Vec<Object>,
+    bytes_allocated: usize,
+    next_gc: usize,
+    config: GcConfig,
+}
+
+// held weakly so that objects outside of cycles are still freed as soon as
+// their last reference goes away
+enum Object {
+    Environment(Weak<RefCell<Environment>>),
+    Function(Weak<Function>),
+    Class(Weak<Class>),
+    Instance(Weak<Instance>),
+}
+
+// an allocated object kept alive while collecting
+enum Live {
+    Environment(Rc<RefCell<Environment>>),
+    Function(Rc<Function>),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
+}
+
+impl Default for Collector {
+    fn default() -> Self {
+        Collector::new(GcConfig::default())
+    }
+}
+
+impl Collector {
+    pub fn new(config: GcConfig) -> Self {
+        Collector {
+            objects: Vec::new(),
+            bytes_allocated: 0,
+            next_gc: config.initial_threshold,
+            config,
+        }
+    }
+
+    pub fn config(&self) -> GcConfig {
+        self.config
+    }
+
+    pub fn environment(&mut self,
+                       environment: Environment) -> Rc<RefCell<Environment>> {
+        let environment = Rc::new(RefCell::new(environment));
+        self.alloc(Object::Environment(Rc::downgrade(&environment)));
+        environment
+    }
+
+    pub fn function(&mut self, function: Function) -> Rc<Function> {
+        let function = Rc::new(function);
+        self.alloc(Object::Function(Rc::downgrade(&function)));
+        function
+    }
+
+    pub fn class(&mut self, class: Class) -> Rc<Class> {
+        let class = Rc::new(class);
+        self.alloc(Object::Class(Rc::downgrade(&class)));
+        class
+    }
+
+    pub fn instance(&mut self, instance: Instance) -> Rc<Instance> {
+        let instance = Rc::new(instance);
+        self.alloc(Object::Instance(Rc::downgrade(&instance)));
+        instance
+    }
+
+    // the new object is held by the caller, so it survives the collection
+    fn alloc(&mut self, object: Object) {
+        self.bytes_allocated += object.size();
+        self.objects.push(object);
+
+        if self.config.stress || self.bytes_allocated > self.next_gc {
+            self.collect();
+        }
+    }
+
+    // frees the cycles that can't be reached from Rust anymore
+    pub fn collect(&mut self) {
+        let live: Vec<Live> = self.objects.iter()
+            .filter_map(Object::upgrade)
+            .collect();
+        let indices: HashMap<*const (), usize> = live.iter().enumerate()
+            .map(|(i, object)| (object.address(), i))
+            .collect();
+
+        // strong references that don't come from an allocated object, the
+        // one in `live` left out
+        let mut external: Vec<usize> = live.iter()
+            .map(|object| object.strong_count() - 1)
+            .collect();
+        // allocated objects each one references, `None` when it is borrowed
+        // and can't be looked into
+        let mut children = Vec::with_capacity(live.len());
+        for object in &live {
+            let references = object.references().map(|references| {
+                references.iter()
+                    .filter_map(|address| indices.get(address).copied())
+                    .collect::<Vec<usize>>()
+            });
+            for &child in references.iter().flatten() {
+                external[child] -= 1;
+            }
+            children.push(references);
+        }
+
+        // a borrowed object is in use, so are the objects it references
+        let mut reachable = vec![false; live.len()];
+        let mut gray: Vec<usize> = (0..live.len())
+            .filter(|&i| external[i] > 0 || children[i].is_none())
+            .collect();
+        while let Some(i) = gray.pop() {
+            if mem::replace(&mut reachable[i], true) {
+                continue;
+            }
+            gray.extend(children[i].iter().flatten()
+                .filter(|&&child| !reachable[child]));
+        }
+
+        // every cycle goes through an environment or the fields of an
+        // instance, emptying them frees the whole garbage once `live` is
+        // dropped
+        for (object, _) in live.iter().zip(&reachable)
+            .filter(|(_, &reachable)| !reachable) {
+            object.clear();
+        }
+        drop(live);
+
+        self.objects.retain(Object::is_alive);
+        self.bytes_allocated = self.objects.iter().map(Object::size).sum();
+        self.next_gc = self.config.initial_threshold
+            .max(self.bytes_allocated * self.config.grow_factor);
+    }
+}
+
+impl Object {
+    fn upgrade(&self) -> Option<Live> {
+        match self {
+            Object::Environment(e) => e.upgrade().map(Live::Environment),
+            Object::Function(f) => f.upgrade().map(Live::Function),
+            Object::Class(c) => c.upgrade().map(Live::Class),
+            Object::Instance(i) => i.upgrade().map(Live::Instance),
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        match self {
+            Object::Environment(e) => e.strong_count() > 0,
+            Object::Function(f) => f.strong_count() > 0,
+            Object::Class(c) => c.strong_count() > 0,
+            Object::Instance(i) => i.strong_count() > 0,
+        }
+    }
+
+    // estimate of the memory it takes, only used to pace the collections
+    fn size(&self) -> usize {
+        match self {
+            Object::Environment(_) => mem::size_of::<Environment>(),
+            Object::Function(_) => mem::size_of::<Function>(),
+            Object::Class(_) => mem::size_of::<Class>(),
+            Object::Instance(_) => mem::size_of::<Instance>(),
+        }
+    }
+}
+
+impl Live {
+    fn address(&self) -> *const () {
+        match self {
+            Live::Environment(e) => Rc::as_ptr(e) as *const (),
+            Live::Function(f) => Rc::as_ptr(f) as *const (),
+            Live::Class(c) => Rc::as_ptr(c) as *const (),
+            Live::Instance(i) => Rc::as_ptr(i) as *const (),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Live::Environment(e) => Rc::strong_count(e),
+            Live::Function(f) => Rc::strong_count(f),
+            Live::Class(c) => Rc::strong_count(c),
+            Live::Instance(i) => Rc::strong_count(i),
+        }
+    }
+
+    // addresses of the objects it holds a strong reference to, once for
+    // every reference
+    fn references(&self) -> Option<Vec<*const ()>> {
+        let mut references = Vec::new();
+        match self {
+            Live::Environment(environment) => {
+                let environment = environment.try_borrow_mut().ok()?;
+                for value in environment.values.values()
+                    .chain(&environment.slots) {
+                    references.extend(value_address(value));
+                }
+                if let Some(enclosing) = &environment.enclosing {
+                    references.push(Rc::as_ptr(enclosing) as *const ());
+                }
+            },
+            Live::Function(function) => {
+                references.push(Rc::as_ptr(&function.closure) as *const ());
+            },
+            Live::Class(class) => {
+                if let Some(superclass) = &class.superclass {
+                    references.push(Rc::as_ptr(superclass) as *const ());
+                }
+                for method in class.methods.values() {
+                    references.push(Rc::as_ptr(method) as *const ());
+                }
+            },
+            Live::Instance(instance) => {
+                references.push(Rc::as_ptr(&instance.class) as *const ());
+                let fields = instance.fields.try_borrow_mut().ok()?;
+                references.extend(fields.values().filter_map(value_address));
+            },
+        }
+        Some(references)
+    }
+
+    // nothing is freed while `live` holds every object, so dropping the
+    // references doesn't run into a borrowed object
+    fn clear(&self) {
+        match self {
+            Live::Environment(environment) => {
+                let mut environment = environment.borrow_mut();
+                environment.values.clear();
+                environment.slots.clear();
+                environment.enclosing = None;
+            },
+            Live::Instance(instance) => instance.fields.borrow_mut().clear(),
+            Live::Function(_) | Live::Class(_) => {},
+        }
+    }
+}
+
+fn value_address(value: &Value) -> Option<*const ()> {
+    match value {
+        Value::Function(f) => Some(Rc::as_ptr(f) as *const ()),
+        Value::Class(c) => Some(Rc::as_ptr(c) as *const ()),
+        Value::Instance(i) => Some(Rc::as_ptr(i) as *const ()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::token::Token;
+    use crate::token::token_type::{Literal, TokenType};
+
+    fn function(name: &str,
+                closure: Rc<RefCell<Environment>>,
+                collector: &mut Collector) -> Rc<Function> {
+        let name = Token {
+            token_type:
+                TokenType::LITERAL(Literal::IDENTIFIER(name.to_string())),
+            lexeme: name.to_string(),
+            line: 1,
+            column: 1,
+            start: 0,
+            end: name.len(),
+        };
+        collector.function(Function {
+            name,
+            params: vec![],
+            body: Rc::new(vec![]),
+            closure,
+            is_initializer: false,
+        })
+    }
+
+    // a local scope holding a closure that closes over it
+    fn closure_cycle(collector: &mut Collector) -> Rc<RefCell<Environment>> {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        let scope =
+            collector.environment(Environment::from_enclosing(globals));
+        let f = function("f", Rc::clone(&scope), collector);
+        scope.borrow_mut().define_var(String::from("f"), Value::Function(f));
+        scope
+    }
+
+    fn instance(collector: &mut Collector) -> Rc<Instance> {
+        let class = collector.class(Class {
+            name: String::from("A"),
+            superclass: None,
+            methods: HashMap::new(),
+        });
+        collector.instance(Instance::new(class))
+    }
+
+    #[test]
+    fn unreachable_cycles_are_freed() {
+        let mut collector = Collector::default();
+        let scope = Rc::downgrade(&closure_cycle(&mut collector));
+        let a = instance(&mut collector);
+        a.fields.borrow_mut()
+            .insert(String::from("me"), Value::Instance(Rc::clone(&a)));
+        let instance = Rc::downgrade(&a);
+        drop(a);
+
+        collector.collect();
+        assert!(scope.upgrade().is_none());
+        assert!(instance.upgrade().is_none());
+        assert_eq!(collector.objects.len(), 0);
+        assert_eq!(collector.bytes_allocated, 0);
+    }
+
+    #[test]
+    fn cycles_referenced_from_rust_are_kept() {
+        let mut collector = Collector::default();
+        let scope = closure_cycle(&mut collector);
+
+        collector.collect();
+        assert_eq!(scope.borrow().slots.len(), 1);
+        assert_eq!(collector.objects.len(), 2);
+    }
+
+    #[test]
+    fn objects_reachable_from_a_root_are_kept() {
+        let mut collector = Collector::default();
+        let root = Rc::new(RefCell::new(Environment::new()));
+        let a = instance(&mut collector);
+        let scope = closure_cycle(&mut collector);
+        a.fields.borrow_mut()
+            .insert(String::from("f"), scope.borrow().slots[0].clone());
+        root.borrow_mut().define_var(String::from("a"), Value::Instance(a));
+        let scope = Rc::downgrade(&scope);
+
+        collector.collect();
+        let scope = scope.upgrade().expect("the closure is still reachable");
+        assert_eq!(scope.borrow().slots.len(), 1);
+    }
+
+    #[test]
+    fn borrowed_objects_are_kept() {
+        let mut collector = Collector::default();
+        let scope = closure_cycle(&mut collector);
+        let a = instance(&mut collector);
+        a.fields.borrow_mut()
+            .insert(String::from("me"), Value::Instance(Rc::clone(&a)));
+        let mut borrow = scope.borrow_mut();
+        borrow.define_var(String::from("a"), Value::Instance(a));
+
+        // what the borrowed scope references can't be looked into
+        collector.collect();
+        assert_eq!(borrow.slots.len(), 2);
+        match &borrow.slots[1] {
+            Value::Instance(a) => assert_eq!(a.fields.borrow().len(), 1),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn stress_collects_on_every_allocation() {
+        let mut collector = Collector::new(GcConfig {
+            stress: true,
+            ..GcConfig::default()
+        });
+        let scope = Rc::downgrade(&closure_cycle(&mut collector));
+        assert!(scope.upgrade().is_some());
+
+        instance(&mut collector);
+        assert!(scope.upgrade().is_none());
+    }
+
+    #[test]
+    fn collections_wait_for_the_threshold() {
+        let size = mem::size_of::<Environment>() + mem::size_of::<Function>();
+        let mut collector = Collector::new(GcConfig {
+            initial_threshold: 2 * size,
+            grow_factor: 2,
+            stress: false,
+        });
+        let first = Rc::downgrade(&closure_cycle(&mut collector));
+        let second = Rc::downgrade(&closure_cycle(&mut collector));
+        assert!(first.upgrade().is_some());
+
+        // going over the threshold collects both cycles
+        let mut kept = vec![closure_cycle(&mut collector)];
+        assert!(first.upgrade().is_none());
+        assert!(second.upgrade().is_none());
+        // the next threshold doesn't go below the initial one
+        assert_eq!(collector.next_gc, 2 * size);
+
+        kept.extend((0..4).map(|_| closure_cycle(&mut collector)));
+        collector.collect();
+        assert_eq!(collector.objects.len(), 10);
+        assert_eq!(collector.next_gc, 10 * size);
+    }
+}
@@ -1,10 +1,23 @@
+use crate::ast::slot::Slot;
 use crate::token::token::Token;
 use crate::token::value::Value;
 
+use super::callable::{NativeFn, NativeFunction};
+use super::native;
+
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
+// Globals are looked up by name as they can be referenced before being
+// declared, every other scope stores its variables in declaration order and
+// is accessed through the slots computed by the resolver.
+#[derive(Default)]
 pub struct Environment {
     pub values: HashMap<String, Value>,
+    pub slots: Vec<Value>,
+    // parent scope, `None` only for the global environment
+    pub enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
 pub struct RunTimeError {
@@ -12,41 +25,105 @@ pub struct RunTimeError {
     pub message: String,
 }
 
-impl Default for Environment {
-    fn default() -> Self {
-        Environment { values: HashMap::new() }
-    }
-} 
-
 impl Environment {
     pub fn new() -> Self {
-       Environment { values: HashMap::new() } 
+        Environment::default()
+    }
+
+    // global scope with the built-in native functions such as `clock`
+    pub fn with_builtins() -> Self {
+        let mut environment = Environment::new();
+        native::define_builtins(&mut environment);
+        environment
+    }
+
+    pub fn from_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment { enclosing: Some(enclosing), ..Environment::default() }
     }
 
     pub fn assign(&mut self, name: Token, value: Value) -> Result<(), RunTimeError>{
-        match self.values.get(&name.lexeme) {
-            Some(_) => {
-                *self.values.get_mut(&name.lexeme).unwrap() = value;
-                Ok(())
-            },
-            None => return Err(RunTimeError {
-                name: name.clone(),
+        if let Some(v) = self.values.get_mut(&name.lexeme) {
+            *v = value;
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(RunTimeError {
                 message: format!("Undefined variable '{}'.", name.lexeme),
+                name,
             }),
         }
     }
 
+    pub fn assign_at(&mut self, slot: Slot, value: Value) {
+        if slot.depth == 0 {
+            self.slots[slot.index] = value;
+        } else {
+            self.ancestor(slot.depth).borrow_mut().slots[slot.index] = value;
+        }
+    }
+
+    // always defines in the innermost scope, shadowing any outer variable
+    // with the same name, local scopes must define their variables in the
+    // same order the resolver declared them
     pub fn define_var(&mut self, name: String, value: Value) {
-        self.values.insert(name, value);
+        if self.enclosing.is_none() {
+            self.values.insert(name, value);
+        } else {
+            self.slots.push(value);
+        }
+    }
+
+    // exposes a Rust function to scripts under `name`, hosts use it to add
+    // their own helpers to the global scope
+    pub fn define_native(&mut self,
+                         name: &str,
+                         arity: usize,
+                         function: NativeFn) {
+        let native = NativeFunction {
+            name: name.to_string(),
+            arity,
+            function,
+        };
+        self.define_var(name.to_string(),
+                        Value::NativeFunction(Rc::new(native)));
     }
 
-    pub fn get_var(&mut self, name: &Token) -> Result<Value, RunTimeError> {
-        match self.values.get(&name.lexeme) {
-            Some(v) => Ok((*v).clone()),
-            None => return Err(RunTimeError {
+    pub fn get_var(&self, name: &Token) -> Result<Value, RunTimeError> {
+        if let Some(v) = self.values.get(&name.lexeme) {
+            return Ok(v.clone());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get_var(name),
+            None => Err(RunTimeError {
                 name: name.clone(),
                 message: format!("Undefined variable '{}'.", name.lexeme),
             }),
         }
     }
+
+    pub fn get_at(&self, slot: Slot) -> Value {
+        if slot.depth == 0 {
+            self.slots[slot.index].clone()
+        } else {
+            self.ancestor(slot.depth).borrow().slots[slot.index].clone()
+        }
+    }
+
+    // scope `depth` levels above this one, the resolver guarantees it exists
+    fn ancestor(&self, depth: usize) -> Rc<RefCell<Environment>> {
+        let mut environment = Rc::clone(
+            self.enclosing.as_ref().expect("resolved scope depth too large")
+        );
+        for _ in 1..depth {
+            let enclosing = Rc::clone(
+                environment.borrow().enclosing.as_ref()
+                    .expect("resolved scope depth too large")
+            );
+            environment = enclosing;
+        }
+        environment
+    }
 }
@@ -1,43 +1,121 @@
-use crate::runtime_error;
 use crate::ast::node::*;
+use crate::ast::slot::Slot;
+use crate::diagnostic::diagnostic::{Code, Diagnostic, Diagnostics};
 use crate::token::token::Token;
 use crate::token::token_type::TokenType;
 use crate::token::value::Value;
 
+use super::callable;
+use super::collector::Collector;
 use super::environment::*;
+use super::output::{self, SharedOutput};
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
 
 // NOTE: struct A; works too apart from struct A {} 
 
-// https://www.youtube.com/watch?v=juIINGuZyBc : Rust Lifetime
-pub struct Interpreter<'a> {
-    environment: &'a mut Environment,
+// deepest call nesting before reporting a stack overflow, the same as the
+// virtual machine
+pub const CALLS_MAX: usize = 4096;
+
+// Rust stack the calls may use before reporting a stack overflow. Every call
+// takes a few dozen Rust frames, so this is reached well before `CALLS_MAX`,
+// but it leaves half of the 2 MB of a spawned thread to the host. Threads
+// with a larger stack can raise it with `with_stack_budget`.
+pub const STACK_BUDGET: usize = 1024 * 1024;
+
+pub struct Interpreter {
+    // outermost scope, holds every variable the resolver left unresolved
+    globals: Rc<RefCell<Environment>>,
+    // innermost scope, blocks swap it out and restore it on exit
+    environment: Rc<RefCell<Environment>>,
+    // where `print` writes to
+    output: SharedOutput,
+    // calls currently running
+    depth: usize,
+    // bytes of Rust stack the calls may use
+    stack_budget: usize,
+    // where the Rust stack was when the outermost call started
+    stack_base: usize,
+    // allocates the environments and objects, freeing their cycles
+    collector: Rc<RefCell<Collector>>,
 }
 
-impl<'a> From<&'a mut Environment> for Interpreter<'a> {
-    fn from(environment: &'a mut Environment) -> Self {
-        Interpreter { environment }
+// prints to stdout
+impl From<Rc<RefCell<Environment>>> for Interpreter {
+    fn from(globals: Rc<RefCell<Environment>>) -> Self {
+        Interpreter::with_output(globals, output::stdout())
     }
 }
 
-impl Interpreter<'_> {
+impl Interpreter {
+    pub fn with_output(globals: Rc<RefCell<Environment>>,
+                       output: SharedOutput) -> Self {
+        Interpreter {
+            environment: Rc::clone(&globals),
+            globals,
+            output,
+            depth: 0,
+            stack_budget: STACK_BUDGET,
+            stack_base: 0,
+            collector: Rc::new(RefCell::new(Collector::default())),
+        }
+    }
+
+    // allocates in `collector`, which can outlive the interpreter so that
+    // the objects a host keeps across interpreters are still collected
+    pub fn with_collector(mut self, collector: Rc<RefCell<Collector>>) -> Self {
+        self.collector = collector;
+        self
+    }
+
+    pub fn with_stack_budget(mut self, bytes: usize) -> Self {
+        self.stack_budget = bytes;
+        self
+    }
 
-    fn evaluate(&mut self, expr: &Expr) -> Result<Value, InterpreterError> {
+    pub fn evaluate(&mut self,
+                    expr: &Expr) -> Result<Value, InterpreterError> {
         (*expr).accept(self)
     }
 
-    pub fn interpret(&mut self, stmts: &[Stmt]) {
+    // a runtime error is reported and only aborts the statement it
+    // happened in
+    pub fn interpret(&mut self, stmts: &[Stmt], diagnostics: &mut Diagnostics) {
         for stmt in stmts {
             if let Err(error) = self.execute(stmt) {
-                runtime_error(&error.token, error.message)
+                diagnostics.report(Diagnostic::from(error));
             }
         }
     }
 
-    fn is_truthy(&self, value: &Value) -> Value {
+    // runs the statements up to the first runtime error, which is returned
+    // instead of being reported. The value is the one of the last statement
+    // when it is an expression statement and nil otherwise.
+    pub fn execute_all(&mut self,
+                       stmts: &[Stmt]) -> Result<Value, InterpreterError> {
+        let mut value = Value::Nil;
+        for stmt in stmts {
+            value = match stmt {
+                Stmt::Expression(expression) =>
+                    self.evaluate(&expression.expression)?,
+                _ => {
+                    self.execute(stmt)?;
+                    Value::Nil
+                },
+            };
+        }
+        Ok(value)
+    }
+
+    // false and nil are falsey, everything else is truthy
+    fn is_truthy(&self, value: &Value) -> bool {
         match *value {
-            Value::Nil => Value::Bool(false),
-            Value::Bool(b) => Value::Bool(b),
-            _ => Value::Bool(true)
+            Value::Nil => false,
+            Value::Bool(b) => b,
+            _ => true
         }
     }
 
@@ -50,9 +128,95 @@ impl Interpreter<'_> {
         }
     }
 
-    fn execute(&mut self, stmt: &Stmt) -> Result<Value, InterpreterError>{
+    fn execute(&mut self, stmt: &Stmt) -> Result<Flow, InterpreterError>{
         stmt.accept(self)
     }
+
+    fn execute_block(
+        &mut self,
+        stmts: &[Stmt],
+        environment: Environment
+    ) -> Result<Flow, InterpreterError> {
+        let environment = self.collector.borrow_mut().environment(environment);
+        let previous = std::mem::replace(&mut self.environment, environment);
+
+        let mut result = Ok(Flow::Next);
+        for stmt in stmts {
+            result = self.execute(stmt);
+            if !matches!(result, Ok(Flow::Next)) {
+                break;
+            }
+        }
+
+        // restore the enclosing scope even if a statement failed
+        self.environment = previous;
+        result
+    }
+
+    fn look_up_variable(
+        &self,
+        name: &Token,
+        slot: &Cell<Option<Slot>>
+    ) -> Result<Value, InterpreterError> {
+        match slot.get() {
+            Some(slot) => Ok(self.environment.borrow().get_at(slot)),
+            None => Ok(self.globals.borrow().get_var(name)?),
+        }
+    }
+
+    fn call_function(&mut self,
+                     function: &callable::Function,
+                     arguments: Vec<Value>) -> Result<Value, InterpreterError> {
+        let mut environment =
+            Environment::from_enclosing(Rc::clone(&function.closure));
+        for (param, argument) in function.params.iter().zip(arguments) {
+            environment.define_var(param.lexeme.clone(), argument);
+        }
+
+        let flow = self.execute_block(&function.body, environment)?;
+
+        if function.is_initializer {
+            // `bind` defines `this` as the only variable in the closure of
+            // every method
+            return Ok(function.closure.borrow().slots[0].clone());
+        }
+
+        match flow {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(Value::Nil),
+        }
+    }
+
+    fn call_class(&mut self,
+                  class: Rc<callable::Class>,
+                  arguments: Vec<Value>) -> Result<Value, InterpreterError> {
+        let instance = self.collector.borrow_mut()
+            .instance(callable::Instance::new(Rc::clone(&class)));
+
+        if let Some(initializer) = class.find_method("init") {
+            let initializer = initializer
+                .bind(Rc::clone(&instance), &mut self.collector.borrow_mut());
+            self.call_function(&initializer, arguments)?;
+        }
+
+        Ok(Value::Instance(instance))
+    }
+}
+
+// address of a local, tells how deep the Rust stack is
+#[inline(never)]
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+// how control leaves a statement, anything but `Next` unwinds the enclosing
+// blocks up to the statement handling it
+pub enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Value),
 }
 
 
@@ -61,6 +225,30 @@ pub struct InterpreterError {
     pub message: String,
 }
 
+impl InterpreterError {
+    // error raised by a native function, the interpreter reports it at the
+    // call site
+    pub fn native(message: &str) -> Self {
+        InterpreterError {
+            token: Token {
+                token_type: TokenType::EOF,
+                lexeme: String::new(),
+                line: 0,
+                column: 0,
+                start: 0,
+                end: 0,
+            },
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<InterpreterError> for Diagnostic {
+    fn from(error: InterpreterError) -> Self {
+        Diagnostic::error(Code::Runtime, error.token.span(), error.message)
+    }
+}
+
 impl From<RunTimeError> for InterpreterError {
     fn from(error: RunTimeError) -> Self {
         InterpreterError {
@@ -71,13 +259,19 @@ impl From<RunTimeError> for InterpreterError {
 }
 
 
-impl Visitor for Interpreter<'_> {
+impl Visitor for Interpreter {
     type R = Result<Value, InterpreterError>;
 
     fn visit_assign(&mut self, assign: &Assign) -> Self::R {
         let value = self.evaluate(&assign.value)?;
-        self.environment.assign(assign.name.clone(), value.clone())?; 
-        return Ok(value);
+        match assign.slot.get() {
+            Some(slot) =>
+                self.environment.borrow_mut().assign_at(slot, value.clone()),
+            None => self.globals
+                .borrow_mut()
+                .assign(assign.name.clone(), value.clone())?,
+        }
+        Ok(value)
     }
 
     fn visit_binary (&mut self, binary: &Binary) -> Self::R {
@@ -137,14 +331,160 @@ impl Visitor for Interpreter<'_> {
 
     }
 
+    fn visit_call (&mut self, call: &Call) -> Self::R {
+        let callee = self.evaluate(&call.callee)?;
+
+        let mut arguments = vec![];
+        for argument in &call.arguments {
+            arguments.push(self.evaluate(argument)?);
+        }
+
+        let arity = match &callee {
+            Value::Function(function) => function.arity(),
+            Value::NativeFunction(native) => native.arity,
+            Value::Class(class) => class.arity(),
+            _ => return Err(InterpreterError {
+                token: call.paren.clone(),
+                message: String::from("Can only call functions and classes."),
+            }),
+        };
+
+        if arguments.len() != arity {
+            return Err(InterpreterError {
+                token: call.paren.clone(),
+                message: format!(
+                    "Expected {} arguments but got {}.",
+                    arity,
+                    arguments.len()
+                ),
+            });
+        }
+
+        // the stack grows down on most platforms, but not all of them
+        let position = stack_position();
+        if self.depth == 0 {
+            self.stack_base = position;
+        }
+        if self.depth == CALLS_MAX
+            || self.stack_base.abs_diff(position) > self.stack_budget {
+            return Err(InterpreterError {
+                token: call.paren.clone(),
+                message: String::from("Stack overflow."),
+            });
+        }
+
+        self.depth += 1;
+        let result = match callee {
+            Value::Function(function) =>
+                self.call_function(&function, arguments),
+            Value::NativeFunction(native) =>
+                (native.function)(self, &arguments).map_err(|mut error| {
+                    if matches!(error.token.token_type, TokenType::EOF) {
+                        error.token = call.paren.clone();
+                    }
+                    error
+                }),
+            Value::Class(class) => self.call_class(class, arguments),
+            _ => unreachable!("non callable values are rejected above"),
+        };
+        self.depth -= 1;
+        result
+    }
+
+    fn visit_get (&mut self, get: &Get) -> Self::R {
+        match self.evaluate(&get.object)? {
+            Value::Instance(instance) =>
+                callable::Instance::get(&instance, &get.name,
+                                        &mut self.collector.borrow_mut())
+                    .ok_or_else(|| InterpreterError {
+                        token: get.name.clone(),
+                        message: format!(
+                            "Undefined property '{}'.", get.name.lexeme
+                        ),
+                    }),
+            _ => Err(InterpreterError {
+                token: get.name.clone(),
+                message: String::from("Only instances have properties."),
+            }),
+        }
+    }
+
     fn visit_grouping (&mut self, grouping: &Grouping) -> Self::R {
         self.evaluate(&grouping.expression)
     }
 
+    fn visit_interpolation (&mut self, interpolation: &Interpolation)
+                            -> Self::R {
+        let mut string = String::new();
+        for part in &interpolation.parts {
+            string += &self.evaluate(part)?.to_string();
+        }
+        Ok(Value::String(string))
+    }
+
     fn visit_literal (&mut self, literal: &Literal) -> Self::R {
         Ok(Value::from(literal.value.token_type.clone()))
     }
 
+    fn visit_logical (&mut self, logical: &Logical) -> Self::R {
+        let left = self.evaluate(&logical.left)?;
+
+        // short-circuit, returning the operand itself rather than a bool
+        match logical.operator.token_type {
+            TokenType::OR if self.is_truthy(&left) => Ok(left),
+            TokenType::AND if !self.is_truthy(&left) => Ok(left),
+            _ => self.evaluate(&logical.right),
+        }
+    }
+
+    fn visit_set (&mut self, set: &Set) -> Self::R {
+        let object = self.evaluate(&set.object)?;
+
+        if let Value::Instance(instance) = object {
+            let value = self.evaluate(&set.value)?;
+            instance.set(&set.name, value.clone());
+            Ok(value)
+        } else {
+            Err(InterpreterError {
+                token: set.name.clone(),
+                message: String::from("Only instances have fields."),
+            })
+        }
+    }
+
+    fn visit_super (&mut self, super_: &Super) -> Self::R {
+        let slot = super_.slot.get()
+            .expect("the resolver always resolves 'super'");
+        let superclass = self.environment.borrow().get_at(slot);
+        // `this` lives in the scope right inside the one holding `super`
+        let instance = self.environment.borrow().get_at(Slot {
+            depth: slot.depth - 1,
+            index: 0,
+        });
+
+        match (superclass, instance) {
+            (Value::Class(superclass), Value::Instance(instance)) => {
+                let method = superclass.find_method(&super_.method.lexeme)
+                    .ok_or_else(|| InterpreterError {
+                        token: super_.method.clone(),
+                        message: format!(
+                            "Undefined property '{}'.", super_.method.lexeme
+                        ),
+                    })?;
+                let mut collector = self.collector.borrow_mut();
+                let method = method.bind(instance, &mut collector);
+                Ok(Value::Function(collector.function(method)))
+            },
+            // the class declaration only ever binds these to a class and an
+            // instance
+            _ => unreachable!("'super' and 'this' bound to the wrong values"),
+        }
+    }
+
+    fn visit_this (&mut self, this: &This) -> Self::R {
+        self.look_up_variable(&this.keyword, &this.slot)
+    }
+
     fn visit_unary (&mut self, unary: &Unary) -> Self::R {
         let right = self.evaluate(&unary.right)?;
 
@@ -161,18 +501,7 @@ impl Visitor for Interpreter<'_> {
                     }),
                 }
             },
-            TokenType::BANG => {
-                if let Value::Bool(b) = self.is_truthy(&right) {
-                    Ok(Value::Bool(!b))
-                } else {
-                    // always returns a type of Value::Bool
-                    Err(InterpreterError {
-                        token: unary.operator.clone(),
-                        message: String::from("Bang Operator only works on \
-                                              literal")
-                    })
-                }
-            },
+            TokenType::BANG => Ok(Value::Bool(!self.is_truthy(&right))),
             _ => Err(InterpreterError {
                 token: unary.operator.clone(),
                 message: String::from("Unary Operator must be MINUS/BANG.")
@@ -181,22 +510,22 @@ impl Visitor for Interpreter<'_> {
     }
 
     fn visit_variable (&mut self, var: &Variable) -> Self::R {
-        Ok(self.environment.get_var(&var.name)?)
+        self.look_up_variable(&var.name, &var.slot)
     }
 }
 
-impl StmtVisitor for Interpreter<'_> {
-    type R = Result<Value, InterpreterError>;
+impl StmtVisitor for Interpreter {
+    type R = Result<Flow, InterpreterError>;
 
     fn visit_expression (&mut self, expression: &Expression) -> Self::R {
         self.evaluate(&expression.expression)?;
-        Ok(Value::Nil)
+        Ok(Flow::Next)
     }
 
     fn visit_print (&mut self, print: &Print) -> Self::R {
         let value = self.evaluate(&print.expression)?;
-        println!("{} ", value);
-        Ok(Value::Nil)
+        self.output.borrow_mut().print(&value.to_string());
+        Ok(Flow::Next)
     }
 
     fn visit_var (&mut self, var: &Var) -> Self::R {
@@ -205,8 +534,139 @@ impl StmtVisitor for Interpreter<'_> {
             val = self.evaluate(ini)?;
         }
 
-        self.environment.define_var(var.name.lexeme.clone(), val);
-        Ok(Value::Nil)
+        self.environment
+            .borrow_mut()
+            .define_var(var.name.lexeme.clone(), val);
+        Ok(Flow::Next)
+    }
+
+    fn visit_block (&mut self, block: &Block) -> Self::R {
+        let environment =
+            Environment::from_enclosing(Rc::clone(&self.environment));
+        self.execute_block(&block.statements, environment)
     }
-}
 
+    fn visit_if (&mut self, stmt: &If) -> Self::R {
+        let condition = self.evaluate(&stmt.condition)?;
+
+        if self.is_truthy(&condition) {
+            self.execute(&stmt.then_branch)
+        } else if let Some(else_branch) = &stmt.else_branch {
+            self.execute(else_branch)
+        } else {
+            Ok(Flow::Next)
+        }
+    }
+
+    fn visit_while (&mut self, stmt: &While) -> Self::R {
+        loop {
+            let condition = self.evaluate(&stmt.condition)?;
+            if !self.is_truthy(&condition) {
+                break;
+            }
+
+            match self.execute(&stmt.body)? {
+                Flow::Break => break,
+                Flow::Return(value) => return Ok(Flow::Return(value)),
+                Flow::Next | Flow::Continue => {},
+            }
+
+            if let Some(increment) = &stmt.increment {
+                self.evaluate(increment)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn visit_break (&mut self, _stmt: &Break) -> Self::R {
+        Ok(Flow::Break)
+    }
+
+    fn visit_continue (&mut self, _stmt: &Continue) -> Self::R {
+        Ok(Flow::Continue)
+    }
+
+    fn visit_function (&mut self, stmt: &Function) -> Self::R {
+        let function = callable::Function {
+            name: stmt.name.clone(),
+            params: stmt.params.clone(),
+            body: Rc::clone(&stmt.body),
+            closure: Rc::clone(&self.environment),
+            is_initializer: false,
+        };
+
+        let function = self.collector.borrow_mut().function(function);
+        self.environment.borrow_mut().define_var(
+            stmt.name.lexeme.clone(),
+            Value::Function(function)
+        );
+        Ok(Flow::Next)
+    }
+
+    fn visit_return (&mut self, stmt: &Return) -> Self::R {
+        let value = match &stmt.value {
+            Some(value) => self.evaluate(value)?,
+            None => Value::Nil,
+        };
+
+        Ok(Flow::Return(value))
+    }
+
+    fn visit_class (&mut self, stmt: &Class) -> Self::R {
+        let superclass = match &stmt.superclass {
+            Some(expr) => match self.evaluate(expr)? {
+                Value::Class(class) => Some(class),
+                _ => {
+                    let token = match &**expr {
+                        Expr::Variable(v) => v.name.clone(),
+                        _ => stmt.name.clone(),
+                    };
+                    return Err(InterpreterError {
+                        token,
+                        message: String::from("Superclass must be a class."),
+                    });
+                },
+            },
+            None => None,
+        };
+
+        // methods of a subclass close over an extra scope holding `super`
+        let closure = match &superclass {
+            Some(superclass) => {
+                let mut environment =
+                    Environment::from_enclosing(Rc::clone(&self.environment));
+                environment.define_var(String::from("super"),
+                                       Value::Class(Rc::clone(superclass)));
+                self.collector.borrow_mut().environment(environment)
+            },
+            None => Rc::clone(&self.environment),
+        };
+
+        let mut methods = HashMap::new();
+        for method in &stmt.methods {
+            let function = callable::Function {
+                name: method.name.clone(),
+                params: method.params.clone(),
+                body: Rc::clone(&method.body),
+                closure: Rc::clone(&closure),
+                is_initializer: method.name.lexeme == "init",
+            };
+            let function = self.collector.borrow_mut().function(function);
+            methods.insert(method.name.lexeme.clone(), function);
+        }
+
+        let class = callable::Class {
+            name: stmt.name.lexeme.clone(),
+            superclass,
+            methods,
+        };
+
+        // defined only once the class is complete, methods refer to it
+        // through their closure so they see it all the same
+        let class = self.collector.borrow_mut().class(class);
+        self.environment
+            .borrow_mut()
+            .define_var(stmt.name.lexeme.clone(), Value::Class(class));
+        Ok(Flow::Next)
+    }
+}
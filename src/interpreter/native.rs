@@ -0,0 +1,29 @@
+use crate::token::value::Value;
+
+use super::environment::Environment;
+use super::interpreter::{Interpreter, InterpreterError};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// defines every built-in native function in the global scope
+pub fn define_builtins(globals: &mut Environment) {
+    globals.define_native("clock", 0, clock);
+}
+
+// seconds since the Unix epoch, meant for measuring elapsed time. The
+// `clock` of the virtual machine returns it too.
+pub fn seconds_since_epoch() -> Result<f64, String> {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| String::from("Clock is set before 1970."))?;
+    Ok(elapsed.as_secs_f64())
+}
+
+fn clock(
+    _interpreter: &mut Interpreter,
+    _arguments: &[Value]
+) -> Result<Value, InterpreterError> {
+    seconds_since_epoch()
+        .map(Value::Number)
+        .map_err(|message| InterpreterError::native(&message))
+}
@@ -0,0 +1,40 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Destination of the text printed by `print` statements. The interpreter
+// and the virtual machine share it with their host, which can keep a handle
+// to read what a script printed or redirect it somewhere else than stdout.
+pub trait Output {
+    // called once per `print` statement, without the trailing newline
+    fn print(&mut self, text: &str);
+}
+
+pub type SharedOutput = Rc<RefCell<dyn Output>>;
+
+// default output, one line per `print`
+pub struct Stdout;
+
+impl Output for Stdout {
+    fn print(&mut self, text: &str) {
+        println!("{}", text);
+    }
+}
+
+// keeps every printed line
+impl Output for Vec<String> {
+    fn print(&mut self, text: &str) {
+        self.push(text.to_string());
+    }
+}
+
+// appends every printed line, newline included
+impl Output for String {
+    fn print(&mut self, text: &str) {
+        self.push_str(text);
+        self.push('\n');
+    }
+}
+
+pub fn stdout() -> SharedOutput {
+    Rc::new(RefCell::new(Stdout))
+}
@@ -0,0 +1,264 @@
+use crate::ast::node::*;
+use crate::ast::slot::Slot;
+use crate::diagnostic::diagnostic::{Code, Diagnostic, Diagnostics};
+use crate::token::{span::Span, token::Token};
+
+use std::cell::Cell;
+use std::collections::HashMap;
+
+// Static pass run between parsing and interpreting. It walks the same scopes
+// the interpreter will create at runtime and records on every variable use
+// which declaration it refers to, so lookups don't depend on the dynamic
+// state of the environment chain.
+pub struct Resolver<'a> {
+    // one entry per local scope, maps a name to its slot index, whether
+    // its initializer has finished resolving and where it was declared,
+    // globals are not tracked
+    scopes: Vec<HashMap<String, (usize, bool, Span)>>,
+    diagnostics: &'a mut Diagnostics,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(diagnostics: &'a mut Diagnostics) -> Self {
+        Resolver { scopes: vec![], diagnostics }
+    }
+
+    pub fn resolve(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            stmt.accept(self);
+        }
+    }
+
+    pub fn resolve_expr(&mut self, expr: &Expr) {
+        expr.accept(self)
+    }
+
+    fn resolve_function(&mut self, function: &Function) {
+        // parameters and the body share a single scope, as in
+        // `Interpreter::call_function`
+        self.begin_scope();
+        for param in &function.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve(&function.body);
+        self.end_scope();
+    }
+
+    // looks the name up from the innermost scope outwards, names not found
+    // are left unresolved and treated as globals
+    fn resolve_local(&mut self, slot: &Cell<Option<Slot>>, name: &str) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some((index, _, _)) = scope.get(name) {
+                slot.set(Some(Slot { depth, index: *index }));
+                return;
+            }
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            if let Some((_, _, previous)) = scope.get(&name.lexeme) {
+                let diagnostic = Diagnostic::error_at(
+                    Code::Resolve,
+                    name,
+                    String::from(
+                        "Already a variable with this name in this scope."
+                    ),
+                ).with_label(*previous, "previous declaration here");
+                self.diagnostics.report(diagnostic);
+                return;
+            }
+
+            let index = scope.len();
+            scope.insert(name.lexeme.clone(), (index, false, name.span()));
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            if let Some(entry) = scope.get_mut(&name.lexeme) {
+                entry.1 = true;
+            }
+        }
+    }
+
+    // declares a name the user can't write, like `this` or `super`
+    fn define_implicit(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let index = scope.len();
+            scope.insert(String::from(name), (index, true, Span::default()));
+        }
+    }
+}
+
+impl Visitor for Resolver<'_> {
+    type R = ();
+
+    fn visit_assign (&mut self, assign: &Assign) -> Self::R {
+        self.resolve_expr(&assign.value);
+        self.resolve_local(&assign.slot, &assign.name.lexeme);
+    }
+
+    fn visit_binary (&mut self, binary: &Binary) -> Self::R {
+        self.resolve_expr(&binary.left);
+        self.resolve_expr(&binary.right);
+    }
+
+    fn visit_call (&mut self, call: &Call) -> Self::R {
+        self.resolve_expr(&call.callee);
+        for argument in &call.arguments {
+            self.resolve_expr(argument);
+        }
+    }
+
+    fn visit_get (&mut self, get: &Get) -> Self::R {
+        self.resolve_expr(&get.object);
+    }
+
+    fn visit_grouping (&mut self, grouping: &Grouping) -> Self::R {
+        self.resolve_expr(&grouping.expression);
+    }
+
+    fn visit_interpolation (&mut self, interpolation: &Interpolation)
+                            -> Self::R {
+        for part in &interpolation.parts {
+            self.resolve_expr(part);
+        }
+    }
+
+    fn visit_literal (&mut self, _literal: &Literal) -> Self::R {}
+
+    fn visit_logical (&mut self, logical: &Logical) -> Self::R {
+        self.resolve_expr(&logical.left);
+        self.resolve_expr(&logical.right);
+    }
+
+    fn visit_set (&mut self, set: &Set) -> Self::R {
+        self.resolve_expr(&set.value);
+        self.resolve_expr(&set.object);
+    }
+
+    fn visit_super (&mut self, super_: &Super) -> Self::R {
+        self.resolve_local(&super_.slot, "super");
+    }
+
+    fn visit_this (&mut self, this: &This) -> Self::R {
+        self.resolve_local(&this.slot, "this");
+    }
+
+    fn visit_unary (&mut self, unary: &Unary) -> Self::R {
+        self.resolve_expr(&unary.right);
+    }
+
+    fn visit_variable (&mut self, variable: &Variable) -> Self::R {
+        if let Some(scope) = self.scopes.last() {
+            if let Some((_, false, declared)) =
+                scope.get(&variable.name.lexeme) {
+                let diagnostic = Diagnostic::error_at(
+                    Code::Resolve,
+                    &variable.name,
+                    String::from(
+                        "Can't read local variable in its own initializer."
+                    ),
+                ).with_label(*declared, "variable declared here");
+                self.diagnostics.report(diagnostic);
+            }
+        }
+
+        self.resolve_local(&variable.slot, &variable.name.lexeme);
+    }
+}
+
+impl StmtVisitor for Resolver<'_> {
+    type R = ();
+
+    fn visit_expression (&mut self, expression: &Expression) -> Self::R {
+        self.resolve_expr(&expression.expression);
+    }
+
+    fn visit_print (&mut self, print: &Print) -> Self::R {
+        self.resolve_expr(&print.expression);
+    }
+
+    fn visit_var (&mut self, var: &Var) -> Self::R {
+        self.declare(&var.name);
+        if let Some(initializer) = &var.initializer {
+            self.resolve_expr(initializer);
+        }
+        self.define(&var.name);
+    }
+
+    fn visit_block (&mut self, block: &Block) -> Self::R {
+        self.begin_scope();
+        self.resolve(&block.statements);
+        self.end_scope();
+    }
+
+    fn visit_if (&mut self, if_: &If) -> Self::R {
+        self.resolve_expr(&if_.condition);
+        if_.then_branch.accept(self);
+        if let Some(else_branch) = &if_.else_branch {
+            else_branch.accept(self);
+        }
+    }
+
+    fn visit_while (&mut self, while_: &While) -> Self::R {
+        self.resolve_expr(&while_.condition);
+        while_.body.accept(self);
+        if let Some(increment) = &while_.increment {
+            self.resolve_expr(increment);
+        }
+    }
+
+    fn visit_break (&mut self, _break: &Break) -> Self::R {}
+
+    fn visit_continue (&mut self, _continue: &Continue) -> Self::R {}
+
+    fn visit_function (&mut self, function: &Function) -> Self::R {
+        // defined eagerly so the function can refer to itself recursively
+        self.declare(&function.name);
+        self.define(&function.name);
+        self.resolve_function(function);
+    }
+
+    fn visit_return (&mut self, return_: &Return) -> Self::R {
+        if let Some(value) = &return_.value {
+            self.resolve_expr(value);
+        }
+    }
+
+    fn visit_class (&mut self, class: &Class) -> Self::R {
+        self.declare(&class.name);
+        self.define(&class.name);
+
+        if let Some(superclass) = &class.superclass {
+            self.resolve_expr(superclass);
+
+            // mirrors the scope holding `super` created by
+            // `Interpreter::visit_class`
+            self.begin_scope();
+            self.define_implicit("super");
+        }
+
+        for method in &class.methods {
+            // mirrors the scope holding `this` created by `Function::bind`
+            self.begin_scope();
+            self.define_implicit("this");
+            self.resolve_function(method);
+            self.end_scope();
+        }
+
+        if class.superclass.is_some() {
+            self.end_scope();
+        }
+    }
+}
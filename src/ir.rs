@@ -0,0 +1,2 @@
+pub mod instruction;
+pub mod lowering;
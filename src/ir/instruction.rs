@@ -0,0 +1,199 @@
+use crate::token::value::Value;
+
+use std::fmt;
+
+// Linear three-address intermediate representation. Every instruction
+// performs at most one operation on operands that are either constants,
+// named variables or compiler generated temporaries, and control flow is
+// made explicit with labels and jumps.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand {
+    Constant(Value),
+    Temp(usize),
+    // locals are renamed so that shadowed variables get distinct names,
+    // globals are prefixed with '@'
+    Var(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Label(pub usize);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    Move { dest: Operand, src: Operand },
+    Binary { dest: Operand, op: BinaryOp, left: Operand, right: Operand },
+    Unary { dest: Operand, op: UnaryOp, operand: Operand },
+    Call { dest: Operand, callee: Operand, arguments: Vec<Operand> },
+    // concatenates the parts of an interpolated string, formatted as `print`
+    // would
+    Interpolate { dest: Operand, parts: Vec<Operand> },
+    GetProperty { dest: Operand, object: Operand, name: String },
+    SetProperty { object: Operand, name: String, value: Operand },
+    GetSuper { dest: Operand, method: String },
+    // creates a closure over the function lowered under `function`
+    Closure { dest: Operand, function: String },
+    Class {
+        dest: Operand,
+        name: String,
+        superclass: Option<Operand>,
+        methods: Vec<String>,
+    },
+    Print(Operand),
+    Label(Label),
+    Jump(Label),
+    JumpIfFalse(Operand, Label),
+    JumpIfTrue(Operand, Label),
+    Return(Option<Operand>),
+}
+
+pub struct IrFunction {
+    // unique among the functions of a program, methods are qualified with
+    // their class name
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<Instruction>,
+}
+
+// the top-level script comes first, followed by every function declared in
+// it in the order they were found
+pub struct Program {
+    pub functions: Vec<IrFunction>,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Constant(Value::String(s)) => write!(f, "{:?}", s),
+            Operand::Constant(value) => write!(f, "{}", value),
+            Operand::Temp(index) => write!(f, "t{}", index),
+            Operand::Var(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnaryOp::Negate => write!(f, "-"),
+            UnaryOp::Not => write!(f, "!"),
+        }
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "L{}", self.0)
+    }
+}
+
+fn join(operands: &[Operand]) -> String {
+    operands.iter()
+        .map(|operand| operand.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Move { dest, src } => write!(f, "{} = {}", dest, src),
+            Instruction::Binary { dest, op, left, right } =>
+                write!(f, "{} = {} {} {}", dest, left, op, right),
+            Instruction::Unary { dest, op, operand } =>
+                write!(f, "{} = {}{}", dest, op, operand),
+            Instruction::Call { dest, callee, arguments } =>
+                write!(f, "{} = call {}({})", dest, callee, join(arguments)),
+            Instruction::Interpolate { dest, parts } =>
+                write!(f, "{} = interpolate({})", dest, join(parts)),
+            Instruction::GetProperty { dest, object, name } =>
+                write!(f, "{} = {}.{}", dest, object, name),
+            Instruction::SetProperty { object, name, value } =>
+                write!(f, "{}.{} = {}", object, name, value),
+            Instruction::GetSuper { dest, method } =>
+                write!(f, "{} = super.{}", dest, method),
+            Instruction::Closure { dest, function } =>
+                write!(f, "{} = closure {}", dest, function),
+            Instruction::Class { dest, name, superclass, methods } => {
+                write!(f, "{} = class {}", dest, name)?;
+                if let Some(superclass) = superclass {
+                    write!(f, " < {}", superclass)?;
+                }
+                write!(f, " [{}]", methods.join(", "))
+            },
+            Instruction::Print(operand) => write!(f, "print {}", operand),
+            Instruction::Label(label) => write!(f, "{}:", label),
+            Instruction::Jump(label) => write!(f, "goto {}", label),
+            Instruction::JumpIfFalse(condition, label) =>
+                write!(f, "if_false {} goto {}", condition, label),
+            Instruction::JumpIfTrue(condition, label) =>
+                write!(f, "if_true {} goto {}", condition, label),
+            Instruction::Return(Some(value)) => write!(f, "return {}", value),
+            Instruction::Return(None) => write!(f, "return"),
+        }
+    }
+}
+
+impl fmt::Display for IrFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "function {}({}):", self.name, self.params.join(", "))?;
+        for instruction in &self.body {
+            // labels stand out from the instructions
+            match instruction {
+                Instruction::Label(_) => writeln!(f, "  {}", instruction)?,
+                _ => writeln!(f, "    {}", instruction)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,625 @@
+use crate::ast::node::*;
+use crate::token::token::Token;
+use crate::token::token_type::TokenType;
+use crate::token::value::Value;
+
+use super::instruction::*;
+
+use std::collections::HashMap;
+
+// labels `continue` and `break` jump to inside the innermost loop
+struct LoopLabels {
+    continue_label: Label,
+    break_label: Label,
+}
+
+struct FunctionBuilder {
+    function: IrFunction,
+    is_initializer: bool,
+    next_temp: usize,
+    next_label: usize,
+    loops: Vec<LoopLabels>,
+}
+
+// Lowers resolved statements into a `Program`. Variable reads are always
+// copied into a temporary so that the order in which operands are evaluated
+// is visible in the output, closures are not converted and simply refer to
+// the variables of the enclosing functions by name.
+pub struct Lowering {
+    functions: Vec<IrFunction>,
+    builders: Vec<FunctionBuilder>,
+    // innermost last, maps a source name to its name in the IR
+    scopes: Vec<HashMap<String, String>>,
+    // number of locals and functions declared so far with a given name, used
+    // to make the IR names unique
+    declared: HashMap<String, usize>,
+}
+
+impl Default for Lowering {
+    fn default() -> Self {
+        Lowering::new()
+    }
+}
+
+impl Lowering {
+    pub fn new() -> Self {
+        Lowering {
+            functions: vec![],
+            builders: vec![],
+            scopes: vec![],
+            declared: HashMap::new(),
+        }
+    }
+
+    pub fn lower(mut self, stmts: &[Stmt]) -> Program {
+        self.begin_function(String::from("<script>"), vec![], false);
+        for stmt in stmts {
+            stmt.accept(&mut self);
+        }
+        self.end_function();
+
+        // the script is finished last but listed first
+        let script = self.functions.pop().unwrap();
+        self.functions.insert(0, script);
+        Program { functions: self.functions }
+    }
+
+    fn lower_expr(&mut self, expr: &Expr) -> Operand {
+        expr.accept(self)
+    }
+
+    fn builder(&mut self) -> &mut FunctionBuilder {
+        self.builders.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.builder().function.body.push(instruction);
+    }
+
+    fn new_temp(&mut self) -> Operand {
+        let builder = self.builder();
+        builder.next_temp += 1;
+        Operand::Temp(builder.next_temp - 1)
+    }
+
+    fn new_label(&mut self) -> Label {
+        let builder = self.builder();
+        builder.next_label += 1;
+        Label(builder.next_label - 1)
+    }
+
+    // `name` if it hasn't been used yet, `name.N` otherwise
+    fn unique_name(&mut self, name: &str) -> String {
+        let count = self.declared.entry(name.to_string()).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            name.to_string()
+        } else {
+            format!("{}.{}", name, *count - 1)
+        }
+    }
+
+    fn declare(&mut self, name: &Token) -> Operand {
+        if self.scopes.is_empty() {
+            return Operand::Var(format!("@{}", name.lexeme));
+        }
+
+        let unique = self.unique_name(&name.lexeme);
+        self.scopes.last_mut().unwrap()
+            .insert(name.lexeme.clone(), unique.clone());
+        Operand::Var(unique)
+    }
+
+    fn variable(&self, name: &str) -> Operand {
+        for scope in self.scopes.iter().rev() {
+            if let Some(unique) = scope.get(name) {
+                return Operand::Var(unique.clone());
+            }
+        }
+        Operand::Var(format!("@{}", name))
+    }
+
+    fn read_variable(&mut self, name: &str) -> Operand {
+        let dest = self.new_temp();
+        let src = self.variable(name);
+        self.emit(Instruction::Move { dest: dest.clone(), src });
+        dest
+    }
+
+    fn begin_function(&mut self,
+                      name: String,
+                      params: Vec<String>,
+                      is_initializer: bool) {
+        self.builders.push(FunctionBuilder {
+            function: IrFunction { name, params, body: vec![] },
+            is_initializer,
+            next_temp: 0,
+            next_label: 0,
+            loops: vec![],
+        });
+    }
+
+    fn end_function(&mut self) {
+        let is_initializer = self.builder().is_initializer;
+        // every function ends with an explicit return
+        if !matches!(self.builder().function.body.last(),
+                     Some(Instruction::Return(_))) {
+            self.emit(Instruction::Return(if is_initializer {
+                Some(Operand::Var(String::from("this")))
+            } else {
+                None
+            }));
+        }
+
+        let builder = self.builders.pop().unwrap();
+        self.functions.push(builder.function);
+    }
+
+    // lowers the body into its own IR function and returns its name
+    fn function(&mut self,
+                function: &Function,
+                name: String,
+                is_initializer: bool) -> String {
+        let name = self.unique_name(&name);
+
+        self.scopes.push(HashMap::new());
+        let params = function.params.iter()
+            .map(|param| match self.declare(param) {
+                Operand::Var(name) => name,
+                _ => unreachable!("locals are declared as variables"),
+            })
+            .collect();
+
+        self.begin_function(name.clone(), params, is_initializer);
+        for stmt in function.body.iter() {
+            stmt.accept(self);
+        }
+        self.end_function();
+        self.scopes.pop();
+
+        name
+    }
+}
+
+impl Visitor for Lowering {
+    type R = Operand;
+
+    fn visit_assign (&mut self, assign: &Assign) -> Self::R {
+        let value = self.lower_expr(&assign.value);
+        let dest = self.variable(&assign.name.lexeme);
+        self.emit(Instruction::Move { dest, src: value.clone() });
+        value
+    }
+
+    fn visit_binary (&mut self, binary: &Binary) -> Self::R {
+        let left = self.lower_expr(&binary.left);
+        let right = self.lower_expr(&binary.right);
+
+        let op = match binary.operator.token_type {
+            TokenType::PLUS => BinaryOp::Add,
+            TokenType::MINUS => BinaryOp::Subtract,
+            TokenType::STAR => BinaryOp::Multiply,
+            TokenType::SLASH => BinaryOp::Divide,
+            TokenType::EqualEqual => BinaryOp::Equal,
+            TokenType::BangEqual => BinaryOp::NotEqual,
+            TokenType::GREATER => BinaryOp::Greater,
+            TokenType::GreaterEqual => BinaryOp::GreaterEqual,
+            TokenType::LESS => BinaryOp::Less,
+            _ => BinaryOp::LessEqual,
+        };
+
+        let dest = self.new_temp();
+        self.emit(Instruction::Binary { dest: dest.clone(), op, left, right });
+        dest
+    }
+
+    fn visit_call (&mut self, call: &Call) -> Self::R {
+        let callee = self.lower_expr(&call.callee);
+        let arguments = call.arguments.iter()
+            .map(|argument| self.lower_expr(argument))
+            .collect();
+
+        let dest = self.new_temp();
+        self.emit(Instruction::Call { dest: dest.clone(), callee, arguments });
+        dest
+    }
+
+    fn visit_get (&mut self, get: &Get) -> Self::R {
+        let object = self.lower_expr(&get.object);
+        let dest = self.new_temp();
+        self.emit(Instruction::GetProperty {
+            dest: dest.clone(),
+            object,
+            name: get.name.lexeme.clone(),
+        });
+        dest
+    }
+
+    fn visit_grouping (&mut self, grouping: &Grouping) -> Self::R {
+        self.lower_expr(&grouping.expression)
+    }
+
+    fn visit_interpolation (&mut self, interpolation: &Interpolation)
+                            -> Self::R {
+        let parts = interpolation.parts.iter()
+            .map(|part| self.lower_expr(part))
+            .collect();
+
+        let dest = self.new_temp();
+        self.emit(Instruction::Interpolate { dest: dest.clone(), parts });
+        dest
+    }
+
+    fn visit_literal (&mut self, literal: &Literal) -> Self::R {
+        Operand::Constant(Value::from(literal.value.token_type.clone()))
+    }
+
+    fn visit_logical (&mut self, logical: &Logical) -> Self::R {
+        let left = self.lower_expr(&logical.left);
+        let dest = self.new_temp();
+        let end = self.new_label();
+
+        self.emit(Instruction::Move { dest: dest.clone(), src: left });
+        self.emit(match logical.operator.token_type {
+            TokenType::OR => Instruction::JumpIfTrue(dest.clone(), end),
+            _ => Instruction::JumpIfFalse(dest.clone(), end),
+        });
+
+        let right = self.lower_expr(&logical.right);
+        self.emit(Instruction::Move { dest: dest.clone(), src: right });
+        self.emit(Instruction::Label(end));
+        dest
+    }
+
+    fn visit_set (&mut self, set: &Set) -> Self::R {
+        let object = self.lower_expr(&set.object);
+        let value = self.lower_expr(&set.value);
+        self.emit(Instruction::SetProperty {
+            object,
+            name: set.name.lexeme.clone(),
+            value: value.clone(),
+        });
+        value
+    }
+
+    fn visit_super (&mut self, super_: &Super) -> Self::R {
+        let dest = self.new_temp();
+        self.emit(Instruction::GetSuper {
+            dest: dest.clone(),
+            method: super_.method.lexeme.clone(),
+        });
+        dest
+    }
+
+    fn visit_this (&mut self, _this: &This) -> Self::R {
+        self.read_variable("this")
+    }
+
+    fn visit_unary (&mut self, unary: &Unary) -> Self::R {
+        let operand = self.lower_expr(&unary.right);
+        let op = match unary.operator.token_type {
+            TokenType::MINUS => UnaryOp::Negate,
+            _ => UnaryOp::Not,
+        };
+
+        let dest = self.new_temp();
+        self.emit(Instruction::Unary { dest: dest.clone(), op, operand });
+        dest
+    }
+
+    fn visit_variable (&mut self, variable: &Variable) -> Self::R {
+        self.read_variable(&variable.name.lexeme)
+    }
+}
+
+impl StmtVisitor for Lowering {
+    type R = ();
+
+    fn visit_expression (&mut self, expression: &Expression) -> Self::R {
+        self.lower_expr(&expression.expression);
+    }
+
+    fn visit_print (&mut self, print: &Print) -> Self::R {
+        let value = self.lower_expr(&print.expression);
+        self.emit(Instruction::Print(value));
+    }
+
+    fn visit_var (&mut self, var: &Var) -> Self::R {
+        let value = match &var.initializer {
+            Some(initializer) => self.lower_expr(initializer),
+            None => Operand::Constant(Value::Nil),
+        };
+
+        // declared after the initializer, which can't refer to it
+        let dest = self.declare(&var.name);
+        self.emit(Instruction::Move { dest, src: value });
+    }
+
+    fn visit_block (&mut self, block: &Block) -> Self::R {
+        self.scopes.push(HashMap::new());
+        for stmt in &block.statements {
+            stmt.accept(self);
+        }
+        self.scopes.pop();
+    }
+
+    fn visit_if (&mut self, if_: &If) -> Self::R {
+        let else_label = self.new_label();
+        let end = self.new_label();
+
+        let condition = self.lower_expr(&if_.condition);
+        self.emit(Instruction::JumpIfFalse(condition, else_label));
+        if_.then_branch.accept(self);
+        self.emit(Instruction::Jump(end));
+
+        self.emit(Instruction::Label(else_label));
+        if let Some(else_branch) = &if_.else_branch {
+            else_branch.accept(self);
+        }
+        self.emit(Instruction::Label(end));
+    }
+
+    fn visit_while (&mut self, while_: &While) -> Self::R {
+        let start = self.new_label();
+        let continue_label = self.new_label();
+        let break_label = self.new_label();
+
+        self.emit(Instruction::Label(start));
+        let condition = self.lower_expr(&while_.condition);
+        self.emit(Instruction::JumpIfFalse(condition, break_label));
+
+        self.builder().loops.push(LoopLabels { continue_label, break_label });
+        while_.body.accept(self);
+        self.builder().loops.pop();
+
+        self.emit(Instruction::Label(continue_label));
+        if let Some(increment) = &while_.increment {
+            self.lower_expr(increment);
+        }
+        self.emit(Instruction::Jump(start));
+        self.emit(Instruction::Label(break_label));
+    }
+
+    fn visit_break (&mut self, _break: &Break) -> Self::R {
+        let label = self.builder().loops.last().unwrap().break_label;
+        self.emit(Instruction::Jump(label));
+    }
+
+    fn visit_continue (&mut self, _continue: &Continue) -> Self::R {
+        let label = self.builder().loops.last().unwrap().continue_label;
+        self.emit(Instruction::Jump(label));
+    }
+
+    fn visit_function (&mut self, function: &Function) -> Self::R {
+        // declared first so the body can refer to itself
+        let dest = self.declare(&function.name);
+        let name = self.function(function, function.name.lexeme.clone(),
+                                 false);
+        self.emit(Instruction::Closure { dest, function: name });
+    }
+
+    fn visit_return (&mut self, return_: &Return) -> Self::R {
+        let value = match &return_.value {
+            Some(value) => Some(self.lower_expr(value)),
+            None if self.builder().is_initializer =>
+                Some(Operand::Var(String::from("this"))),
+            None => None,
+        };
+        self.emit(Instruction::Return(value));
+    }
+
+    fn visit_class (&mut self, class: &Class) -> Self::R {
+        let superclass = class.superclass.as_ref()
+            .map(|superclass| self.lower_expr(superclass));
+        let dest = self.declare(&class.name);
+
+        // `this` keeps its name inside every method
+        let mut this_scope = HashMap::new();
+        this_scope.insert(String::from("this"), String::from("this"));
+        self.scopes.push(this_scope);
+
+        let methods = class.methods.iter()
+            .map(|method| self.function(
+                method,
+                format!("{}.{}", class.name.lexeme, method.name.lexeme),
+                method.name.lexeme == "init"
+            ))
+            .collect();
+        self.scopes.pop();
+
+        self.emit(Instruction::Class {
+            dest,
+            name: class.name.lexeme.clone(),
+            superclass,
+            methods,
+        });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::diagnostic::Diagnostics;
+    use crate::lox;
+
+    fn lower(source: &str) -> String {
+        let mut diagnostics = Diagnostics::new();
+        let stmts = lox::parse(source, &mut diagnostics)
+            .expect("the source is valid");
+        Lowering::new().lower(&stmts).to_string()
+    }
+
+    #[test]
+    fn short_circuits_jump_over_the_right_operand() {
+        let source = "\
+            var a = true;
+            var b = false;
+            print a and b;
+            print a or b;";
+        assert_eq!(lower(source), "\
+function <script>():
+    @a = true
+    @b = false
+    t0 = @a
+    t1 = t0
+    if_false t1 goto L0
+    t2 = @b
+    t1 = t2
+  L0:
+    print t1
+    t3 = @a
+    t4 = t3
+    if_true t4 goto L1
+    t5 = @b
+    t4 = t5
+  L1:
+    print t4
+    return
+");
+    }
+
+    #[test]
+    fn continue_and_break_jump_to_the_loop_labels() {
+        let source = "\
+            for (var i = 0; i < 10; i = i + 1) {
+              if (i == 2) continue;
+              if (i == 5) break;
+              print i;
+            }";
+        assert_eq!(lower(source), "\
+function <script>():
+    i = 0
+  L0:
+    t0 = i
+    t1 = t0 < 10
+    if_false t1 goto L2
+    t2 = i
+    t3 = t2 == 2
+    if_false t3 goto L3
+    goto L1
+    goto L4
+  L3:
+  L4:
+    t4 = i
+    t5 = t4 == 5
+    if_false t5 goto L5
+    goto L2
+    goto L6
+  L5:
+  L6:
+    t6 = i
+    print t6
+  L1:
+    t7 = i
+    t8 = t7 + 1
+    i = t8
+    goto L0
+  L2:
+    return
+");
+    }
+
+    #[test]
+    fn shadowed_locals_get_numbered() {
+        let source = "\
+            {
+              var x = 1;
+              {
+                var x = 2;
+                print x;
+              }
+              print x;
+            }";
+        assert_eq!(lower(source), "\
+function <script>():
+    x = 1
+    x.1 = 2
+    t0 = x.1
+    print t0
+    t1 = x
+    print t1
+    return
+");
+    }
+
+    #[test]
+    fn closures_refer_to_captured_variables_by_name() {
+        let source = "\
+            fun outer() {
+              var n = 0;
+              fun inner() {
+                n = n + 1;
+                return n;
+              }
+              return inner;
+            }";
+        assert_eq!(lower(source), "\
+function <script>():
+    @outer = closure outer
+    return
+
+function inner.1():
+    t0 = n
+    t1 = t0 + 1
+    n = t1
+    t2 = n
+    return t2
+
+function outer():
+    n = 0
+    inner = closure inner.1
+    t0 = inner
+    return t0
+");
+    }
+
+    #[test]
+    fn classes_lower_their_methods_and_init_returns_this() {
+        let source = "\
+            class A {
+              init(x) {
+                this.x = x;
+                return;
+              }
+              get() {
+                return this.x;
+              }
+            }
+            class B < A {
+              get() {
+                return super.get() + 1;
+              }
+            }
+            print B(1).get();";
+        assert_eq!(lower(source), "\
+function <script>():
+    @A = class A [A.init, A.get]
+    t0 = @A
+    @B = class B < t0 [B.get]
+    t1 = @B
+    t2 = call t1(1)
+    t3 = t2.get
+    t4 = call t3()
+    print t4
+    return
+
+function A.init(x):
+    t0 = this
+    t1 = x
+    t0.x = t1
+    return this
+
+function A.get():
+    t0 = this
+    t1 = t0.x
+    return t1
+
+function B.get():
+    t0 = super.get
+    t1 = call t0()
+    t2 = t1 + 1
+    return t2
+");
+    }
+}
@@ -1,38 +1,101 @@
-use crate::error;
+use crate::diagnostic::diagnostic::{Code, Diagnostic, Diagnostics};
+use crate::token::span::Span;
 use crate::token::token::Token;
 use crate::token::token_type::{ TokenType, Literal, get_token_type };
 
-pub struct Scanner {
+use std::mem;
+
+// line of a triple quoted string
+struct StringLine {
+    // unescaped text
+    text: String,
+    // spaces and tabs the line starts with in the source
+    indent: usize,
+    // nothing but spaces and tabs so far
+    blank: bool,
+}
+
+impl Default for StringLine {
+    fn default() -> Self {
+        StringLine { text: String::new(), indent: 0, blank: true }
+    }
+}
+
+// `${` whose expression is being scanned
+struct OpenInterpolation {
+    // braces opened since the `${` and not closed yet
+    braces: usize,
+    // where an unterminated interpolation is reported
+    span: Span,
+    // number of tokens before the expression, to tell it's empty
+    first_token: usize,
+}
+
+// Splits the source into tokens in a single pass. `start` and `current` are
+// byte offsets into the source, always on character boundaries, so looking
+// at the next character and slicing a lexeme take constant time.
+pub struct Scanner<'a> {
     source: String,
     tokens: Vec<Token>,
-    start: u32,
-    current: u32,
-    line: u32
+    // first byte of the token being scanned
+    start: usize,
+    // byte of the next character
+    current: usize,
+    line: u32,
+    // column of the next character
+    column: u32,
+    // position of the first character of the token being scanned
+    start_line: u32,
+    start_column: u32,
+    // innermost last, interpolations can nest inside interpolated strings
+    interpolations: Vec<OpenInterpolation>,
+    diagnostics: &'a mut Diagnostics,
 }
 
-impl Scanner {
-    pub fn new(source: String) -> Scanner {
+impl<'a> Scanner<'a> {
+    pub fn new(source: String, diagnostics: &'a mut Diagnostics) -> Self {
         Scanner {
             source,
+            diagnostics,
             tokens: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
+            interpolations: vec![],
         }
     }
 
     pub fn scan_tokens(&mut self) -> Vec<Token> {
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column;
             self.scan_token();
         }
 
+        for open in mem::take(&mut self.interpolations) {
+            self.diagnostics.report(
+                Diagnostic::error(
+                    Code::Scan,
+                    open.span,
+                    String::from("Unterminated string interpolation."),
+                )
+                .incomplete()
+            );
+        }
+
         self.tokens.push(Token { 
             token_type: TokenType::EOF,
             lexeme: String::from(""),
-            line: self.line 
+            line: self.line,
+            column: self.column,
+            start: self.current,
+            end: self.current,
         });
-        self.tokens.clone()
+        mem::take(&mut self.tokens)
     }
 
     fn scan_token(&mut self) {
@@ -40,9 +103,22 @@ impl Scanner {
         match c {
             '(' => self.add_token(TokenType::LeftParen),
             ')' => self.add_token(TokenType::RightParen),
-            '{' => self.add_token(TokenType::LeftBrace),
-            '}' => self.add_token(TokenType::RightBrace),
+            '{' => {
+                if let Some(open) = self.interpolations.last_mut() {
+                    open.braces += 1;
+                }
+                self.add_token(TokenType::LeftBrace);
+            },
+            '}' => match self.interpolations.last_mut() {
+                Some(open) if open.braces == 0 => self.close_interpolation(),
+                Some(open) => {
+                    open.braces -= 1;
+                    self.add_token(TokenType::RightBrace);
+                },
+                None => self.add_token(TokenType::RightBrace),
+            },
             ',' => self.add_token(TokenType::COMMA),
+            '.' => self.add_token(TokenType::DOT),
             '-' => self.add_token(TokenType::MINUS),
             '+' => self.add_token(TokenType::PLUS),
             ';' => self.add_token(TokenType::SEMICOLON),
@@ -80,6 +156,8 @@ impl Scanner {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                } else if self.match_char('*') {
+                    self.block_comment();
                 } else {
                     self.add_token(TokenType::SLASH);
                 }
@@ -87,68 +165,74 @@ impl Scanner {
             ' ' => {},
             '\r' => {},
             '\t' => {},
-            '\n' => self.line += 1,
-            '"' => self.string(),
+            '\n' => {},
+            '"' => self.string(false),
             _ => {
-                if c.is_digit(10) {
+                if c == 'r' && self.peek() == '"' {
+                    self.advance();
+                    self.string(true);
+                } else if c.is_ascii_digit() {
                     self.number();
                 } else if self.is_alpha(c) {
                     self.reserved_words();
                 }
                 else {
-                    error(self.line, "Unexpected character.".to_string());
+                    // escaped so that invisible characters show up
+                    self.error(&format!("Unexpected character '{}'.",
+                                        c.escape_debug()));
                 }
             }
         };
     }
 
     fn advance(&mut self) -> char {
-        self.current += 1;
-        if let Some(c) = self.source.chars().nth(self.current as usize - 1) {
-            c
+        // callers check for the end first, past it there's nothing to
+        // consume and '\0' is returned
+        debug_assert!(!self.is_at_end(), "advanced past the end of the source");
+        let c = self.peek();
+        if self.is_at_end() {
+            return c;
+        }
+
+        self.current += c.len_utf8();
+        // lines are counted here so that every token spanning several
+        // lines keeps them up to date
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
         } else {
-            eprintln!("Error: Reached end of source but advance was called.");
-            '\0'
+            self.column += 1;
         }
+        c
     }
 
     fn add_token(&mut self, token: TokenType) {
-        let text = self.source[self.start as usize..self.current as usize]
-            .to_string();
-
-        self.tokens.push(Token{
-            token_type: token,
-            lexeme: text,
-            line: self.line
-        });
+        let text = self.source[self.start..self.current].to_string();
+        self.add_token_lexeme(token, text);
     }
 
-    fn add_token_literal(&mut self, token: TokenType) {
-        let mut text = self.source[self.start as usize..self.current as usize]
-            .to_string();
-
-        if let TokenType::LITERAL(Literal::STRING(_)) = token {
-            text.remove(0);
-            text.remove(text.len() - 1);
-        }
-
+    fn add_token_lexeme(&mut self, token: TokenType, lexeme: String) {
         self.tokens.push(Token{
             token_type: token,
-            lexeme: text,
-            line: self.line
+            lexeme,
+            line: self.start_line,
+            column: self.start_column,
+            start: self.start,
+            end: self.current,
         });
     }
 
     fn is_at_end(&self) -> bool {
-        self.current >= self.source.len() as u32
+        self.current >= self.source.len()
     }
 
+    // identifiers follow Unicode's XID rules, with `_` allowed anywhere
     fn is_alpha(&self, c: char) -> bool {
-        ('a'..='z').contains(&c)|| ('A'..='Z').contains(&c) || c == '_'
+        c == '_' || unicode_ident::is_xid_start(c)
     }
 
     fn is_alpha_numeric(&self, c: char) -> bool {
-        self.is_alpha(c) || c.is_digit(10)
+        c == '_' || unicode_ident::is_xid_continue(c)
     }
 
     fn match_char(&mut self, expected: char) -> bool {
@@ -156,77 +240,329 @@ impl Scanner {
            return false;
         }
 
-        let c = self.source.chars().nth(self.current as usize).unwrap();
-        if c != expected {
+        if self.peek() != expected {
             return false;
         }
 
-        self.current += 1;
+        self.advance();
         true
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
+        self.source[self.current..].chars().next().unwrap_or('\0')
+    }
+
+    // scanner just look ahead atmost one character
+    fn peek_next(&self) -> char {
+        let mut chars = self.source[self.current..].chars();
+        chars.next();
+        chars.next().unwrap_or('\0')
+    }
+
+    // whether the next characters are `s`
+    fn peek_str(&self, s: &str) -> bool {
+        self.source[self.current..].starts_with(s)
+    }
+
+    // called after the opening `/*`, comments nest so that commenting out
+    // code that has comments works
+    fn block_comment(&mut self) {
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                let span = Span {
+                    start: self.start,
+                    end: self.start + 2,
+                    line: self.start_line,
+                    column: self.start_column,
+                };
+                self.diagnostics.report(
+                    Diagnostic::error(
+                        Code::Scan,
+                        span,
+                        String::from("Unterminated block comment."),
+                    )
+                    .with_note("block comments nest, every `/*` needs its \
+                                own `*/`")
+                    .incomplete()
+                );
+                return;
+            }
+
+            if self.peek_str("/*") {
+                self.advance();
+                depth += 1;
+            } else if self.peek_str("*/") {
+                self.advance();
+                depth -= 1;
+            }
+            self.advance();
         }
+    }
 
-        // unwrapping assuming above statement is true
-        return self.source.chars().nth(self.current as usize).unwrap();
+    // called after the opening quote, `raw` strings have no escapes
+    fn string(&mut self, raw: bool) {
+        if self.peek_str("\"\"") {
+            self.advance();
+            self.advance();
+            self.triple_quoted_string(raw);
+        } else {
+            self.string_body(raw, false);
+        }
     }
 
-    // scanner just look ahead atmost one character
-    fn peek_next(&mut self) -> char {
-        if self.current + 1 >= self.source.len() as u32 {
-            return '\0';
+    // Scans up to the closing quote. In a string that isn't raw, a `${`
+    // ends the token as an INTERPOLATION, the expression after it is
+    // scanned as usual and the string is `resumed` after the matching `}`:
+    //
+    //   "a ${b} c ${d} e"  ->  INTERPOLATION("a ") IDENTIFIER(b)
+    //                          InterpolationMiddle(" c ") IDENTIFIER(d)
+    //                          InterpolationEnd(" e")
+    fn string_body(&mut self, raw: bool, resumed: bool) {
+        let mut value = String::new();
+        loop {
+            if self.is_at_end() {
+                // a string left open inside an interpolation is reported
+                // as the interpolation not being terminated
+                if self.interpolations.is_empty() {
+                    self.unterminated_string();
+                }
+                return;
+            }
+
+            match self.advance() {
+                '"' => break,
+                '\\' if !raw => self.escape(&mut value),
+                '$' if !raw && self.peek() == '{' => {
+                    return self.open_interpolation(value, resumed);
+                },
+                c => value.push(c),
+            }
         }
 
-        // unwrapping as above statement is true
-        return self.source.chars().nth((self.current + 1) as usize).unwrap();
+        // the lexeme is the text between the quotes, or the `}` closing an
+        // interpolation and the quote
+        let open = if raw { 2 } else { 1 };
+        let lexeme = self.source[self.start + open..self.current - 1]
+            .to_string();
+        let literal = if resumed {
+            Literal::InterpolationEnd(value)
+        } else {
+            Literal::STRING(value)
+        };
+        self.add_token_lexeme(TokenType::LITERAL(literal), lexeme);
+    }
+
+    // called after the `$` of a `${`, `value` is the string before it
+    fn open_interpolation(&mut self, value: String, resumed: bool) {
+        let span = Span {
+            start: self.current - 1,
+            end: self.current + 1,
+            line: self.line,
+            column: self.column - 1,
+        };
+        self.advance();
+
+        let lexeme = self.source[self.start + 1..self.current - 2]
+            .to_string();
+        let literal = if resumed {
+            Literal::InterpolationMiddle(value)
+        } else {
+            Literal::INTERPOLATION(value)
+        };
+        self.add_token_lexeme(TokenType::LITERAL(literal), lexeme);
+        self.interpolations.push(OpenInterpolation {
+            braces: 0,
+            span,
+            first_token: self.tokens.len(),
+        });
+    }
+
+    // called on the `}` matching a `${`
+    fn close_interpolation(&mut self) {
+        if let Some(open) = self.interpolations.pop() {
+            if open.first_token == self.tokens.len() {
+                let span = open.span.to(self.token_span());
+                self.error_at(span,
+                              "Expect expression in string interpolation.");
+            }
+        }
+        self.string_body(false, true);
     }
 
-    fn string(&mut self) {
-        while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+    // Strings between `"""` can span several lines and are indented along
+    // with the code around them, that indentation isn't part of the value:
+    //
+    //   print """
+    //       Hello,
+    //         World!
+    //       """;
+    //
+    // prints the two lines with `World!` indented by two spaces. A line break
+    // right after the opening quotes and the line of the closing quotes,
+    // when nothing else is on it, are dropped. Then the whitespace every
+    // line that isn't blank starts with, as well as the closing line, is
+    // removed.
+    fn triple_quoted_string(&mut self, raw: bool) {
+        let mut lines = vec![];
+        let mut line = StringLine::default();
+        loop {
+            if self.is_at_end() {
+                if self.interpolations.is_empty() {
+                    self.unterminated_string();
+                }
+                return;
             }
+            if self.peek_str("\"\"\"") {
+                break;
+            }
+
+            match self.advance() {
+                '\n' => lines.push(mem::take(&mut line)),
+                // the line break of a file with Windows line endings
+                '\r' if self.peek() == '\n' => {},
+                '\\' if !raw => {
+                    line.blank = false;
+                    self.escape(&mut line.text);
+                },
+                c @ (' ' | '\t') if line.blank => {
+                    line.indent += 1;
+                    line.text.push(c);
+                },
+                c => {
+                    line.blank = false;
+                    line.text.push(c);
+                },
+            }
+        }
+        lines.push(line);
+        for _ in 0..3 {
             self.advance();
         }
 
+        if lines.len() > 1 && lines[0].blank {
+            lines.remove(0);
+        }
+        let closing = match lines.last() {
+            Some(last) if lines.len() > 1 && last.blank => lines.pop(),
+            _ => None,
+        };
+
+        let indent = lines.iter()
+            .filter(|line| !line.blank)
+            .chain(closing.iter())
+            .map(|line| line.indent)
+            .min()
+            .unwrap_or(0);
+        let value = lines.iter()
+            .map(|line| {
+                if line.blank {
+                    ""
+                } else {
+                    // the indentation is made of single byte characters
+                    &line.text[indent..]
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let open = if raw { 4 } else { 3 };
+        let lexeme = self.source[self.start + open..self.current - 3]
+            .to_string();
+        self.add_token_lexeme(TokenType::LITERAL(Literal::STRING(value)),
+                              lexeme);
+    }
+
+    // called after a backslash in a string, pushes the escaped character
+    fn escape(&mut self, value: &mut String) {
+        let start = self.current - 1;
+        let line = self.line;
+        let column = self.column - 1;
+        // let the string report that it isn't terminated
         if self.is_at_end() {
-            error(self.line, "Unterminated string".to_string());
             return;
         }
+        // the line break is left to the string, which may go on
+        if self.peek() == '\n' || self.peek_str("\r\n") {
+            let span = Span { start, end: self.current, line, column };
+            return self.error_at(span, "Backslash at end of line.");
+        }
 
-        // The closin "
-        self.advance();
+        let escaped = match self.advance() {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '"' => '"',
+            '\\' => '\\',
+            '$' => '$',
+            'u' => match self.unicode_escape() {
+                Ok(c) => c,
+                Err(message) => {
+                    let span = Span { start, end: self.current, line, column };
+                    return self.error_at(span, &message);
+                },
+            },
+            c => {
+                let span = Span { start, end: self.current, line, column };
+                let message = format!("Invalid escape sequence '\\{}'.",
+                                      c.escape_debug());
+                self.diagnostics.report(
+                    Diagnostic::error(Code::Scan, span, message)
+                        .with_note("the escapes are \\n, \\t, \\r, \\\", \\\\, \
+                                    \\$ and \\u{...}")
+                );
+                return;
+            },
+        };
+        value.push(escaped);
+    }
+
+    // the `{...}` part of `\u{...}`, 1 to 6 hexadecimal digits
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let malformed = || String::from(
+            "Invalid Unicode escape, expected 1 to 6 hex digits in '\\u{...}'."
+        );
 
-        let value = self.source[
-            self.start as usize + 1..self.current as usize - 1
-        ].to_string();
+        if !self.match_char('{') {
+            return Err(malformed());
+        }
+        let digits_start = self.current;
+        while self.peek().is_ascii_hexdigit() {
+            self.advance();
+        }
+        let digits = self.source[digits_start..self.current].to_string();
+        if !self.match_char('}') || digits.is_empty() || digits.len() > 6 {
+            return Err(malformed());
+        }
 
-        self.add_token_literal(TokenType::LITERAL(Literal::STRING(value)));
+        u32::from_str_radix(&digits, 16).ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| {
+                format!("Invalid Unicode code point 'U+{}'.",
+                        digits.to_uppercase())
+            })
     }
 
     fn number(&mut self) {
-        while self.peek().is_digit(10) {
+        while self.peek().is_ascii_digit() {
             self.advance();
         }
 
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
 
-            while self.peek().is_digit(10) {
+            while self.peek().is_ascii_digit() {
                 self.advance();
             }
         }
 
         // assuming that the number is valid~ that's why using unwrap()
         // without error handling
-        let value = self.source[
-            self.start as usize..self.current as usize
-        ].parse::<f64>().unwrap();
+        let value = self.source[self.start..self.current]
+            .parse::<f64>()
+            .unwrap();
 
-        self.add_token_literal(TokenType::LITERAL(Literal::NUMBER(value)));
+        self.add_token(TokenType::LITERAL(Literal::NUMBER(value)));
     }
 
     fn reserved_words(&mut self) {
@@ -234,11 +570,57 @@ impl Scanner {
             self.advance();
         }
 
-        let text =
-            self.source[self.start as usize..self.current as usize]
-            .to_string();
+        let text = self.source[self.start..self.current].to_string();
 
         self.add_token(get_token_type(text));
     }
+
+    fn token_span(&self) -> Span {
+        Span {
+            start: self.start,
+            end: self.current,
+            line: self.start_line,
+            column: self.start_column,
+        }
+    }
+
+    fn unterminated_string(&mut self) {
+        self.diagnostics.report(
+            Diagnostic::error(Code::Scan,
+                              self.token_span(),
+                              String::from("Unterminated string"))
+                .incomplete()
+        );
+    }
+
+    // reported at the token being scanned
+    fn error(&mut self, message: &str) {
+        self.error_at(self.token_span(), message);
+    }
+
+    fn error_at(&mut self, span: Span, message: &str) {
+        self.diagnostics.report(
+            Diagnostic::error(Code::Scan, span, message.to_string())
+        );
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backslash_at_end_of_line_points_at_the_backslash() {
+        for source in ["print \"a \\\nb\";", "print \"a \\\r\nb\";",
+                       "print \"\"\"\na \\\nb\"\"\";"] {
+            let mut diagnostics = Diagnostics::new();
+            Scanner::new(source.to_string(), &mut diagnostics).scan_tokens();
+
+            let errors: Vec<_> = diagnostics.iter().collect();
+            assert_eq!(errors.len(), 1, "{:?}", source);
+            assert_eq!(errors[0].message, "Backslash at end of line.");
+            let span = errors[0].span;
+            assert_eq!(&source[span.start..span.end], "\\");
+        }
+    }
+}
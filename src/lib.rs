@@ -0,0 +1,16 @@
+#![allow(clippy::module_inception)]
+
+pub mod ast;
+pub mod diagnostic;
+pub mod harness;
+pub mod interpreter;
+pub mod ir;
+pub mod lox;
+pub mod parser;
+pub mod repl;
+pub mod token;
+pub mod lexer;
+pub mod vm;
+
+pub use lox::{Lox, LoxError};
+pub use token::value::Value;
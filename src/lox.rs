@@ -0,0 +1,194 @@
+use crate::ast::node::{Expr, Stmt};
+use crate::diagnostic::diagnostic::{Diagnostic, Diagnostics};
+use crate::interpreter::{
+    callable::NativeFn,
+    collector::Collector,
+    environment::Environment,
+    interpreter::{Interpreter, STACK_BUDGET},
+    output::{self, SharedOutput},
+    resolver::Resolver,
+};
+use crate::lexer::scanner::Scanner;
+use crate::parser::parser::Parser;
+use crate::token::value::Value;
+use crate::vm::heap::GcConfig;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq)]
+pub enum LoxError {
+    // every error found while scanning, parsing or resolving
+    Compile(Vec<Diagnostic>),
+    Runtime(Box<Diagnostic>),
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoxError::Compile(diagnostics) => {
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", diagnostic)?;
+                }
+                Ok(())
+            },
+            LoxError::Runtime(diagnostic) => write!(f, "{}", diagnostic),
+        }
+    }
+}
+
+impl std::error::Error for LoxError {}
+
+// Scans, parses and resolves a script, the front end shared by every
+// backend. `None` when any of them reported an error.
+pub fn parse(source: &str, diagnostics: &mut Diagnostics) -> Option<Vec<Stmt>> {
+    let tokens = Scanner::new(source.to_string(), diagnostics).scan_tokens();
+    if diagnostics.has_compile_errors() {
+        return None;
+    }
+
+    let stmts = Parser::new(tokens, diagnostics).parse()?;
+    if diagnostics.has_compile_errors() {
+        return None;
+    }
+
+    Resolver::new(diagnostics).resolve(&stmts);
+    if diagnostics.has_compile_errors() {
+        return None;
+    }
+    Some(stmts)
+}
+
+// Embedding entry point running scripts on the tree-walking interpreter.
+// Globals persist across calls to `eval`, so a host can define a function
+// in one call and use it in the next.
+pub struct Lox {
+    globals: Rc<RefCell<Environment>>,
+    output: SharedOutput,
+    collector: Rc<RefCell<Collector>>,
+    stack_budget: usize,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Lox::new()
+    }
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        Lox::with_gc(GcConfig::default())
+    }
+
+    // the collections are paced the same way as on the virtual machine
+    pub fn with_gc(config: GcConfig) -> Self {
+        Lox {
+            globals: Rc::new(RefCell::new(Environment::with_builtins())),
+            output: output::stdout(),
+            collector: Rc::new(RefCell::new(Collector::new(config))),
+            stack_budget: STACK_BUDGET,
+        }
+    }
+
+    // forgets every global, the other settings are kept
+    pub fn reset(&mut self) {
+        let config = self.collector.borrow().config();
+        let output = Rc::clone(&self.output);
+        let stack_budget = self.stack_budget;
+        *self = Lox::with_gc(config);
+        self.output = output;
+        self.stack_budget = stack_budget;
+    }
+
+    // redirects `print`, which goes to stdout by default
+    pub fn set_output(&mut self, output: SharedOutput) {
+        self.output = output;
+    }
+
+    // bytes of stack the calls of a script may use before it fails with
+    // "Stack overflow.", `interpreter::STACK_BUDGET` by default. Hosts that
+    // run scripts on a thread with a larger stack can raise it.
+    pub fn set_stack_budget(&mut self, bytes: usize) {
+        self.stack_budget = bytes;
+    }
+
+    // evaluates a script, the result is the value of its last statement when
+    // that is an expression statement, e.g. `eval("1 + 2;")` gives 3
+    pub fn eval(&mut self, source: &str) -> Result<Value, LoxError> {
+        let mut diagnostics = Diagnostics::new();
+        let stmts = match parse(source, &mut diagnostics) {
+            Some(stmts) => stmts,
+            None => return Err(LoxError::Compile(diagnostics.take())),
+        };
+
+        self.interpreter()
+            .execute_all(&stmts)
+            .map_err(|error| {
+                LoxError::Runtime(Box::new(Diagnostic::from(error)))
+            })
+    }
+
+    // runs a script the way the command line does, every error is reported
+    // as it's found and a runtime error only aborts the statement it
+    // happened in
+    pub fn run(&mut self, source: &str, diagnostics: &mut Diagnostics) {
+        if let Some(stmts) = parse(source, diagnostics) {
+            self.interpreter().interpret(&stmts, diagnostics);
+        }
+    }
+
+    // value of an expression that was parsed on its own, e.g. typed in the
+    // REPL, `None` when an error was reported
+    pub fn evaluate(&mut self,
+                    expression: &Expr,
+                    diagnostics: &mut Diagnostics) -> Option<Value> {
+        Resolver::new(diagnostics).resolve_expr(expression);
+        if diagnostics.has_compile_errors() {
+            return None;
+        }
+
+        match self.interpreter().evaluate(expression) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                diagnostics.report(Diagnostic::from(error));
+                None
+            },
+        }
+    }
+
+    // every global with its value, in no order
+    pub fn globals(&self) -> Vec<(String, Value)> {
+        self.globals.borrow().values.iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().values.get(name).cloned()
+    }
+
+    // defines the global if it doesn't exist yet
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().define_var(name.to_string(), value);
+    }
+
+    // the native only exists on the tree-walker, the virtual machine takes
+    // its own kind through `VM::define_native`
+    pub fn define_native(&mut self,
+                         name: &str,
+                         arity: usize,
+                         function: NativeFn) {
+        self.globals.borrow_mut().define_native(name, arity, function);
+    }
+
+    fn interpreter(&self) -> Interpreter {
+        Interpreter::with_output(Rc::clone(&self.globals),
+                                 Rc::clone(&self.output))
+            .with_collector(Rc::clone(&self.collector))
+            .with_stack_budget(self.stack_budget)
+    }
+}
@@ -1,124 +1,417 @@
-pub mod ast;
-pub mod interpreter;
-pub mod parser;
-pub mod token;
-pub mod lexer;
-
-// use ast::ast_printer::AstPrinter;
-// use ast::node::*;
-use interpreter::{
-    environment::Environment,
-    interpreter::Interpreter
+use rulox::ast::ast_printer::AstPrinter;
+use rulox::ast::node::{Expr, Print, Stmt};
+use rulox::interpreter::resolver::Resolver;
+use rulox::harness::runner::Runner;
+use rulox::lox::{self, Lox};
+use rulox::ir::lowering::Lowering;
+use rulox::parser::parser::Parser;
+use rulox::repl::{command::{Command, HELP}, input};
+use rulox::lexer::scanner::Scanner;
+use rulox::vm::{compiler::Compiler, heap::GcConfig, vm::VM};
+use rulox::diagnostic::{
+    diagnostic::{Diagnostic, Diagnostics},
+    json,
+    render::{ColorChoice, Renderer},
 };
-use parser::parser::Parser;
-use token::token::Token;
-use token::token_type::TokenType;
-use lexer::scanner::Scanner;
+
+use rustyline::{error::ReadlineError, DefaultEditor};
 
 use std::env;
 use std::fs;
-use std::io;
-use std::io::Write; // <--- bring flush() into scope
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::thread;
+use std::time::Instant;
+
+// execution backend chosen on the command line, both keep their globals
+// alive across calls to `run` so the REPL can build on previous lines
+enum Backend {
+    TreeWalker(Lox),
+    Vm(Box<VM>),
+    // prints the intermediate representation instead of running the code
+    DumpIr,
+}
 
-static mut HAD_ERROR: bool = false;
-static mut HAD_RUNTIME_ERROR: bool = false;
+// how diagnostics are written to stderr
+#[derive(Clone, Copy)]
+enum ErrorFormat {
+    // source snippets for people, colored according to the flag
+    Human(bool),
+    // the one line format of the reference lox, used by `rulox test`
+    Short,
+    // one JSON object per line for tools
+    Json,
+}
+
+// the tree-walker recurses on the Rust stack, half of it is enough for
+// `CALLS_MAX` calls in a debug build, the same limit as the virtual machine
+const STACK_SIZE: usize = 512 * 1024 * 1024;
 
 fn main() {
-    let args_count = env::args().count();
+    let main = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_main)
+        .expect("Error starting the interpreter thread");
+    if main.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn run_main() {
+    let mut use_vm = false;
+    let mut dump_ir = false;
+    let mut gc_config = GcConfig::default();
+    // garbage collector flags, kept to forward them to the tests
+    let mut gc_flags = vec![];
+    let mut color = ColorChoice::Auto;
+    let mut error_format = String::from("human");
+    let mut paths = vec![];
+    for arg in env::args().skip(1) {
+        match &arg[..] {
+            "--vm" => use_vm = true,
+            "--dump-ir" => dump_ir = true,
+            "--gc-stress" => {
+                gc_config.stress = true;
+                gc_flags.push(arg);
+            },
+            _ if arg.starts_with("--gc-threshold=") => {
+                match arg["--gc-threshold=".len()..].parse() {
+                    Ok(bytes) => gc_config.initial_threshold = bytes,
+                    Err(_) => usage(),
+                }
+                gc_flags.push(arg);
+            },
+            _ if arg.starts_with("--gc-grow=") => {
+                match arg["--gc-grow=".len()..].parse() {
+                    Ok(factor) if factor >= 1 => gc_config.grow_factor = factor,
+                    _ => usage(),
+                }
+                gc_flags.push(arg);
+            },
+            _ if arg.starts_with("--color=") => {
+                match ColorChoice::parse(&arg["--color=".len()..]) {
+                    Some(choice) => color = choice,
+                    None => usage(),
+                }
+            },
+            _ if arg.starts_with("--error-format=") => {
+                error_format = arg["--error-format=".len()..].to_string();
+            },
+            _ if arg.starts_with("--") => usage(),
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.len() == 2 && paths[0] == "test" {
+        // the flags are forwarded to every script that is run
+        run_tests(&paths[1], Runner::new(current_exe(), gc_flags, use_vm));
+    }
+
+    let backend = if dump_ir {
+        Backend::DumpIr
+    } else if use_vm {
+        Backend::Vm(Box::new(VM::new(gc_config)))
+    } else {
+        let mut lox = Lox::with_gc(gc_config);
+        lox.set_stack_budget(STACK_SIZE / 2);
+        Backend::TreeWalker(lox)
+    };
+
+    let format = match &error_format[..] {
+        "human" => ErrorFormat::Human(color.use_color()),
+        "short" => ErrorFormat::Short,
+        "json" => ErrorFormat::Json,
+        _ => usage(),
+    };
 
-    match args_count {
-        1 => run_prompt(),
-        2 => {
-            run_file(env::args().nth(1).unwrap());
+    match paths.len() {
+        0 => run_prompt(backend, format),
+        1 => run_file(paths.remove(0), backend, format),
+        _ => usage(),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: rulox [--vm | --dump-ir] [GC flags] \
+               [--color=auto|always|never] \
+               [--error-format=human|short|json] [script]\n       \
+               rulox [--vm] [GC flags] test <dir>\n\n\
+               GC flags: --gc-stress --gc-threshold=<bytes> \
+               --gc-grow=<factor>");
+    process::exit(64);
+}
+
+fn current_exe() -> PathBuf {
+    env::current_exe().expect("Error locating the interpreter")
+}
+
+// runs every script under `dir` and exits with 0 when all of them pass
+fn run_tests(dir: &str, runner: Runner) -> ! {
+    let outcomes = match runner.run_dir(Path::new(dir)) {
+        Ok(outcomes) => outcomes,
+        Err(error) => {
+            eprintln!("Error running the tests in {}: {}", dir, error);
+            process::exit(74);
+        },
+    };
+
+    let mut failed = 0;
+    for outcome in &outcomes {
+        if outcome.passed() {
+            continue;
         }
-        _ => {
-            eprintln!("Usage: rulox [script]");
-            process::exit(64);
+
+        failed += 1;
+        println!("FAIL {}", outcome.path.display());
+        for failure in &outcome.failures {
+            println!("     {}", failure);
         }
     }
+    println!("Passed: {} Failed: {}", outcomes.len() - failed, failed);
+
+    process::exit(if failed == 0 { 0 } else { 1 });
 }
 
-fn run_file(path: String) {
-    let contents = fs::read_to_string(path)
+fn run_file(path: String, mut backend: Backend, format: ErrorFormat) {
+    let contents = fs::read_to_string(&path)
         .expect("Error reading script");
-    let mut env = Environment::new();
 
-    run(contents, &mut env);
+    let mut diagnostics =
+        Diagnostics::with_emitter(stderr_emitter(&path, &contents, format));
+    run(contents, &mut backend, &mut diagnostics);
 
-    unsafe{
-        if HAD_ERROR {
-            process::exit(65);
-        } else if HAD_RUNTIME_ERROR {
-            process::exit(70);
-        }
+    if diagnostics.has_compile_errors() {
+        process::exit(65);
+    } else if diagnostics.has_runtime_errors() {
+        process::exit(70);
     }
 }
 
 // READ-EVAL-PRINT-LOOP (REPL)
-fn run_prompt() {
-    let mut env= Environment::new();
+fn run_prompt(mut backend: Backend, format: ErrorFormat) {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(error) => {
+            eprintln!("Error starting the REPL: {}", error);
+            process::exit(74);
+        },
+    };
+    let history = history_path();
+    if let Some(path) = &history {
+        // the file doesn't exist until the first session ends
+        let _ = editor.load_history(path);
+    }
+
+    let mut diagnostics = Diagnostics::new();
+    // lines typed so far for the current statement
+    let mut input = String::new();
     loop {
-        print!("> ");
-        io::stdout().flush().unwrap();
-        let mut line = String::new();
-
-        if io::stdin().read_line(&mut line).is_ok() {
-            run(line, &mut env);
-            unsafe {
-                HAD_ERROR = false;
+        let prompt = if input.is_empty() { "> " } else { ". " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C drops what was typed, Ctrl-D quits
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            },
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                eprintln!("Error reading input: {}", error);
+                break;
+            },
+        };
+
+        // commands fit on one line and aren't looked for in the middle of
+        // a statement
+        if input.is_empty() {
+            if let Some(command) = Command::parse(&line) {
+                let _ = editor.add_history_entry(line.trim());
+                match command {
+                    Ok(command) => run_command(command, &mut backend,
+                                               &mut diagnostics, format),
+                    Err(message) => eprintln!("{}", message),
+                }
+                diagnostics.clear();
+                continue;
             }
-        } else {
-            break;
         }
-    }
-}
 
-fn run(source: String, env: &mut Environment) {
-    // println!("source: {}", source);
+        // an empty line runs an incomplete input anyway, to see what's
+        // missing from it
+        let force = !input.is_empty() && line.trim().is_empty();
+        input.push_str(&line);
+        input.push('\n');
+        // a bare expression is complete even without a `;`
+        let expression = input::parse_expression(&input);
+        if expression.is_none() && !force && input::is_incomplete(&input) {
+            continue;
+        }
 
-    let tokens = Scanner::new(source).scan_tokens();
+        let source = mem::take(&mut input);
+        if !source.trim().is_empty() {
+            let _ = editor.add_history_entry(source.trim_end());
+        }
 
-    unsafe {
-        if HAD_ERROR {
-            return;
+        // snippets point into the input that was just typed
+        diagnostics.set_emitter(stderr_emitter("<repl>", &source, format));
+        match expression {
+            Some(expression) =>
+                run_expression(expression, &mut backend, &mut diagnostics),
+            None => run(source, &mut backend, &mut diagnostics),
         }
+        diagnostics.clear();
     }
 
-    // println!("tokens: {:?}", tokens);
-
-    let mut parser = Parser::new(tokens);
-    if let Some(expr) = parser.parse() {
-        let mut interpreter = Interpreter::from(env);
-        interpreter.interpret(&expr);
+    if let Some(path) = &history {
+        if let Err(error) = editor.save_history(path) {
+            eprintln!("Error saving the history: {}", error);
+        }
     }
 }
 
-pub fn runtime_error(token: &Token, message: String) {
-    eprintln!("[line {}]: {}", token.line, message);
+fn run_command(command: Command,
+               backend: &mut Backend,
+               diagnostics: &mut Diagnostics,
+               format: ErrorFormat) {
+    // the code given to a command is what diagnostics point into
+    match &command {
+        Command::Ast(code) | Command::Tokens(code) | Command::Time(code) => {
+            diagnostics.set_emitter(stderr_emitter("<repl>", code, format));
+        },
+        _ => {},
+    }
+
+    match command {
+        Command::Load(path) => match fs::read_to_string(&path) {
+            Ok(source) => {
+                diagnostics.set_emitter(stderr_emitter(&path, &source, format));
+                run(source, backend, diagnostics);
+            },
+            Err(error) => eprintln!("Error reading {}: {}", path, error),
+        },
+        Command::Env => {
+            let mut globals = match backend {
+                Backend::TreeWalker(lox) => lox.globals().into_iter()
+                    .map(|(name, value)| (name, value.to_string()))
+                    .collect(),
+                Backend::Vm(vm) => vm.globals(),
+                Backend::DumpIr => vec![],
+            };
+            globals.sort();
+            for (name, value) in globals {
+                println!("{} = {}", name, value);
+            }
+        },
+        Command::Reset => match backend {
+            Backend::TreeWalker(lox) => lox.reset(),
+            Backend::Vm(vm) => vm.reset(),
+            Backend::DumpIr => {},
+        },
+        Command::Ast(code) => {
+            if let Some(expression) = input::parse_expression(&code) {
+                println!("{}", AstPrinter::new().print_expr(&expression));
+                return;
+            }
 
-    unsafe {
-        HAD_RUNTIME_ERROR = true;
+            let tokens = Scanner::new(code, diagnostics).scan_tokens();
+            if diagnostics.has_compile_errors() {
+                return;
+            }
+            if let Some(stmts) = Parser::new(tokens, diagnostics).parse() {
+                if !diagnostics.has_compile_errors() {
+                    println!("{}", AstPrinter::new().print(&stmts));
+                }
+            }
+        },
+        Command::Tokens(code) => {
+            for token in Scanner::new(code, diagnostics).scan_tokens() {
+                let position = format!("{}:{}", token.line, token.column);
+                let token_type = token.token_type.to_string();
+                let line = format!("{:<6} {:<13} {}", position, token_type,
+                                   token.lexeme);
+                println!("{}", line.trim_end());
+            }
+        },
+        Command::Time(code) => {
+            let start = Instant::now();
+            match input::parse_expression(&code) {
+                Some(expression) =>
+                    run_expression(expression, backend, diagnostics),
+                None => run(code, backend, diagnostics),
+            }
+            println!("time: {:.3?}", start.elapsed());
+        },
+        Command::Help => println!("{}", HELP),
     }
 }
 
-pub fn error_token(token: &Token, message: String) {
-    match token.token_type {
-        TokenType::EOF => report(token.line, " at end".to_string(), message),
-        _ => report(token.line,
-                    format!(" at '{}'", token.lexeme),
-                    message)
-    }
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".rulox_history"))
 }
 
-pub fn error(line: u32, message: String) {
-    report(line, String::from(""), message);
+// reports every diagnostic on stderr as soon as it is found, rendered
+// in the format chosen on the command line
+fn stderr_emitter(file: &str, source: &str, format: ErrorFormat)
+    -> impl FnMut(&Diagnostic) + 'static {
+    let file = file.to_string();
+    let render: Box<dyn Fn(&Diagnostic) -> String> = match format {
+        ErrorFormat::Human(color) => {
+            let renderer = Renderer::new(&file, source, color);
+            Box::new(move |diagnostic| renderer.render(diagnostic))
+        },
+        ErrorFormat::Short => Box::new(|diagnostic| diagnostic.to_string()),
+        ErrorFormat::Json =>
+            Box::new(move |diagnostic| json::to_json(diagnostic, &file)),
+    };
+    move |diagnostic| eprintln!("{}", render(diagnostic))
 }
 
-fn report(line: u32, where_error: String, message: String) {
-    eprintln!("[line {}] Error{}: {}", line, where_error, message);
-    unsafe {
-        HAD_ERROR = true;
+fn run(source: String, backend: &mut Backend, diagnostics: &mut Diagnostics) {
+    if let Backend::TreeWalker(lox) = backend {
+        return lox.run(&source, diagnostics);
+    }
+
+    if let Some(stmts) = lox::parse(&source, diagnostics) {
+        execute(&stmts, backend, diagnostics);
+    }
+}
+
+// echoes the value of an expression typed in the REPL
+fn run_expression(expression: Box<Expr>,
+                  backend: &mut Backend,
+                  diagnostics: &mut Diagnostics) {
+    if let Backend::TreeWalker(lox) = backend {
+        if let Some(value) = lox.evaluate(&expression, diagnostics) {
+            println!("{}", value);
+        }
+        return;
+    }
+
+    // the other backends only run statements, they print it instead
+    let span = expression.span();
+    let print = [Stmt::Print(Print { expression, span })];
+    Resolver::new(diagnostics).resolve(&print);
+    if !diagnostics.has_compile_errors() {
+        execute(&print, backend, diagnostics);
     }
 }
 
+// runs resolved statements on the backends other than the tree-walker
+fn execute(stmts: &[Stmt], backend: &mut Backend,
+           diagnostics: &mut Diagnostics) {
+    match backend {
+        Backend::Vm(vm) => {
+            let scripts = Compiler::new(vm.heap_mut(), diagnostics)
+                .compile(stmts);
+
+            if diagnostics.has_compile_errors() {
+                return;
+            }
+
+            vm.interpret(scripts, diagnostics);
+        },
+        Backend::DumpIr => print!("{}", Lowering::new().lower(stmts)),
+        Backend::TreeWalker(_) => unreachable!("`Lox` runs its own scripts"),
+    }
+}
@@ -1,25 +1,59 @@
-use crate::error_token;
 use crate::ast::node::*;
+use crate::diagnostic::diagnostic::{Code, Diagnostic, Diagnostics};
+use crate::token::span::Span;
 use crate::token::token::Token;
 use crate::token::token_type;
 use crate::token::token_type::TokenType;
 
+use std::cell::Cell;
+use std::rc::Rc;
+
+// upper bound on parameters and arguments, kept from the reference lox
+const MAX_ARGUMENTS: usize = 255;
+
 // TODO: Check style guide for rust
 
 struct ParseError {}
 
-pub struct Parser {
+// kind of the function body being parsed, used to validate `return`
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+// kind of the class body being parsed, used to validate `this` and `super`
+#[derive(Clone, Copy, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+pub struct Parser<'a> {
     tokens: Vec<Token>,
     // points to the next token to be parsed
     current: usize,
+    // number of enclosing loops, `break` and `continue` are only valid
+    // inside one
+    loop_depth: usize,
+    current_function: FunctionKind,
+    current_class: ClassKind,
+    diagnostics: &'a mut Diagnostics,
 }
 
-impl Parser {
+impl<'a> Parser<'a> {
 
-    pub fn new(tokens: Vec<Token>) -> Parser {
+    pub fn new(tokens: Vec<Token>, diagnostics: &'a mut Diagnostics) -> Self {
         Parser {
             tokens,
+            diagnostics,
             current: 0,
+            loop_depth: 0,
+            current_function: FunctionKind::None,
+            current_class: ClassKind::None,
         }
     }
 
@@ -33,28 +67,147 @@ impl Parser {
         Some(statements)
     }
 
+    // parses a single expression, optionally followed by a `;`, as typed
+    // in the REPL
+    pub fn parse_expression(&mut self) -> Option<Box<Expr>> {
+        let expression = self.expression().ok()?;
+        self.match_token(&[TokenType::SEMICOLON]);
+        if !self.is_at_end() {
+            self.error_at_current("Expect end of expression.");
+            return None;
+        }
+        Some(expression)
+    }
+
     fn declaration(&mut self) -> Result<Stmt, ParseError> {
-        if self.match_token(&[TokenType::VAR]) {
-            match self.var_declaration() {
-                Ok(v) => return Ok(v),
+        let result = if self.match_token(&[TokenType::CLASS]) {
+            self.class_declaration()
+        } else if self.match_token(&[TokenType::FUN]) {
+            self.function(FunctionKind::Function).map(Stmt::Function)
+        } else if self.match_token(&[TokenType::VAR]) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        };
+
+        // skip to the next statement boundary so that parsing can go on and
+        // report further errors
+        if result.is_err() {
+            self.synchronize();
+        }
+        result
+    }
+
+    fn class_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span();
+        let name = self.consume_identifier("Expect class name.")?;
+
+        let superclass = if self.match_token(&[TokenType::LESS]) {
+            let superclass =
+                self.consume_identifier("Expect superclass name.")?;
+            if superclass.lexeme == name.lexeme {
+                self.error(&superclass, "A class can't inherit from itself.");
+            }
+            Some(Box::new(Expr::Variable(Variable {
+                span: superclass.span(),
+                name: superclass,
+                slot: Cell::new(None),
+            })))
+        } else {
+            None
+        };
+
+        self.consume(&TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let kind = if superclass.is_some() {
+            ClassKind::Subclass
+        } else {
+            ClassKind::Class
+        };
+        let enclosing_class = std::mem::replace(&mut self.current_class, kind);
+
+        let mut methods = vec![];
+        let mut result = Ok(());
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let kind = if self.peek().lexeme == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+
+            match self.function(kind) {
+                Ok(method) => methods.push(method),
                 Err(e) => {
-                    self.synchronize();
-                    return Err(e);
+                    result = Err(e);
+                    break;
                 }
-            };
+            }
+        }
+        self.current_class = enclosing_class;
+        result?;
+
+        self.consume(&TokenType::RightBrace, "Expect '}' after class body.")?;
+        Ok(Stmt::Class(Class {
+            name,
+            superclass,
+            methods,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn function(&mut self, kind: FunctionKind) -> Result<Function, ParseError> {
+        // only used for error messages
+        let kind_name = match kind {
+            FunctionKind::Function => "function",
+            _ => "method",
         };
 
-        self.statement()
+        // functions start at `fun`, methods at their name
+        let start = match kind {
+            FunctionKind::Function => self.previous().span(),
+            _ => self.peek().span(),
+        };
+        let name = self.consume_identifier(
+            &format!("Expect {} name.", kind_name)
+        )?;
+
+        self.consume(&TokenType::LeftParen,
+                     &format!("Expect '(' after {} name.", kind_name))?;
+        let mut params = vec![];
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    self.error_at_current(&format!(
+                        "Can't have more than {} parameters.", MAX_ARGUMENTS
+                    ));
+                }
+                params.push(self.consume_identifier("Expect parameter name.")?);
+
+                if !self.match_token(&[TokenType::COMMA]) {
+                    break;
+                }
+            }
+        }
+        self.consume(&TokenType::RightParen, "Expect ')' after parameters.")?;
+
+        self.consume(&TokenType::LeftBrace,
+                     &format!("Expect '{{' before {} body.", kind_name))?;
+
+        // loops outside the function don't make `break` valid inside it
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let enclosing_function = std::mem::replace(&mut self.current_function,
+                                                   kind);
+        let body = self.block();
+        self.current_function = enclosing_function;
+        self.loop_depth = loop_depth;
+
+        let body = Rc::new(body?);
+        Ok(Function { name, params, body, span: self.span_from(start) })
     }
 
     fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
-        let name = match &self.peek().token_type {
-            TokenType::LITERAL(token_type::Literal::IDENTIFIER(_))
-            => Ok(self.advance().clone()),
-            _ => Err(self.error(self.peek(), "Expect variable name."))
-        };
-
-        let name = name?;
+        let start = self.previous().span();
+        let name = self.consume_identifier("Expect variable name.")?;
 
         let mut initializer = None;
         if self.match_token(&[TokenType::EQUAL]) {
@@ -63,26 +216,210 @@ impl Parser {
 
         self.consume(&TokenType::SEMICOLON, "Expected ';' after variable \
                                                 declaration.")?;
-        Ok(Stmt::Var(Var {name, initializer}))
+        Ok(Stmt::Var(Var { name, initializer, span: self.span_from(start) }))
     }
 
     fn statement(&mut self) -> Result<Stmt, ParseError> {
+        if self.match_token(&[TokenType::BREAK]) {
+            return self.break_statement();
+        }
+        if self.match_token(&[TokenType::CONTINUE]) {
+            return self.continue_statement();
+        }
+        if self.match_token(&[TokenType::FOR]) {
+            return self.for_statement();
+        }
+        if self.match_token(&[TokenType::IF]) {
+            return self.if_statement();
+        }
         if self.match_token(&[TokenType::PRINT]) {
             return self.print_statement();
         }
+        if self.match_token(&[TokenType::RETURN]) {
+            return self.return_statement();
+        }
+        if self.match_token(&[TokenType::WHILE]) {
+            return self.while_statement();
+        }
+        if self.match_token(&[TokenType::LeftBrace]) {
+            let start = self.previous().span();
+            let statements = self.block()?;
+            return Ok(Stmt::Block(Block {
+                statements,
+                span: self.span_from(start),
+            }));
+        }
         self.expression_statement()
     }
 
+    fn break_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        if self.loop_depth == 0 {
+            // reported without unwinding, the statement itself is well formed
+            self.error(&keyword, "Can't use 'break' outside of a loop.");
+        }
+        self.consume(&TokenType::SEMICOLON, "Expect ';' after 'break'.")?;
+        let span = self.span_from(keyword.span());
+        Ok(Stmt::Break(Break { keyword, span }))
+    }
+
+    fn continue_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        if self.loop_depth == 0 {
+            self.error(&keyword, "Can't use 'continue' outside of a loop.");
+        }
+        self.consume(&TokenType::SEMICOLON, "Expect ';' after 'continue'.")?;
+        let span = self.span_from(keyword.span());
+        Ok(Stmt::Continue(Continue { keyword, span }))
+    }
+
+    // for loops are desugared into a while loop wrapped in a block holding
+    // the initializer, the increment is kept on the while so that
+    // `continue` still runs it
+    fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span();
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.match_token(&[TokenType::SEMICOLON]) {
+            None
+        } else if self.match_token(&[TokenType::VAR]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(&TokenType::SEMICOLON) {
+            // an omitted condition loops forever, it takes an empty span
+            // right before the ';'
+            let next = self.peek();
+            let value = Token {
+                token_type: TokenType::TRUE,
+                lexeme: String::from("true"),
+                end: next.start,
+                ..next.clone()
+            };
+            Box::new(Expr::Literal(Literal { span: value.span(), value }))
+        } else {
+            self.expression()?
+        };
+        self.consume(&TokenType::SEMICOLON,
+                     "Expect ';' after loop condition.")?;
+
+        let increment = if self.check(&TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(&TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        let body = Box::new(self.loop_body()?);
+        let span = self.span_from(start);
+        let mut stmt = Stmt::While(While { condition, body, increment, span });
+
+        if let Some(initializer) = initializer {
+            stmt = Stmt::Block(Block {
+                statements: vec![initializer, stmt],
+                span,
+            });
+        }
+
+        Ok(stmt)
+    }
+
+    // assumes the opening '{' has already been consumed
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut statements: Vec<Stmt> = vec![];
+
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if let Ok(decl) = self.declaration() {
+                statements.push(decl);
+            }
+        }
+
+        self.consume(&TokenType::RightBrace, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span();
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = Box::new(self.statement()?);
+        // a dangling else binds to the nearest if
+        let else_branch = if self.match_token(&[TokenType::ELSE]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If(If {
+            condition,
+            then_branch,
+            else_branch,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        if self.current_function == FunctionKind::None {
+            self.error(&keyword, "Can't return from top-level code.");
+        }
+
+        let value = if self.check(&TokenType::SEMICOLON) {
+            None
+        } else {
+            if self.current_function == FunctionKind::Initializer {
+                self.error(&keyword,
+                           "Can't return a value from an initializer.");
+            }
+            Some(self.expression()?)
+        };
+
+        self.consume(&TokenType::SEMICOLON, "Expect ';' after return value.")?;
+        let span = self.span_from(keyword.span());
+        Ok(Stmt::Return(Return { keyword, value, span }))
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span();
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after condition.")?;
+        let body = Box::new(self.loop_body()?);
+
+        Ok(Stmt::While(While {
+            condition,
+            body,
+            increment: None,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn loop_body(&mut self) -> Result<Stmt, ParseError> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
+    }
+
     fn print_statement(&mut self) -> Result<Stmt, ParseError>  {
+        let start = self.previous().span();
         let expression = self.expression()?;
         self.consume(&TokenType::SEMICOLON, "Expected ';' after value.")?;
-        Ok(Stmt::Print(Print { expression }))
+        Ok(Stmt::Print(Print { expression, span: self.span_from(start) }))
     }
 
     fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.peek().span();
         let expression = self.expression()?;
         self.consume(&TokenType::SEMICOLON, "Expected ';' after expression.")?;
-        Ok(Stmt::Expression(Expression { expression }))
+        Ok(Stmt::Expression(Expression {
+            expression,
+            span: self.span_from(start),
+        }))
 
     }
 
@@ -131,15 +468,36 @@ impl Parser {
         if self.check(token_type) {
             Ok(self.advance())
         } else {
-           return Err(self.error(self.peek(), message));
+           Err(self.error_at_current(message))
         }
     }
 
-    fn error(&self, token: &Token, message: &str) -> ParseError {
-        error_token(token, message.to_string());
+    fn consume_identifier(&mut self,
+                          message: &str) -> Result<Token, ParseError> {
+        match &self.peek().token_type {
+            TokenType::LITERAL(token_type::Literal::IDENTIFIER(_))
+            => Ok(self.advance().clone()),
+            _ => Err(self.error_at_current(message))
+        }
+    }
+
+    // span from `start` up to the most recently consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous().span())
+    }
+
+    fn error(&mut self, token: &Token, message: &str) -> ParseError {
+        self.diagnostics.report(
+            Diagnostic::error_at(Code::Parse, token, message.to_string())
+        );
         ParseError {}
     }
 
+    fn error_at_current(&mut self, message: &str) -> ParseError {
+        let token = self.peek().clone();
+        self.error(&token, message)
+    }
+
     fn synchronize(&mut self) {
         self.advance();
 
@@ -171,27 +529,73 @@ impl Parser {
     }
 
     fn assingment(&mut self) -> Result<Box<Expr>, ParseError> {
-        let expr = self.equality()?;
+        let expr = self.or()?;
 
         if self.match_token(&[TokenType::EQUAL]) {
             let equals = self.previous().clone();
             let value = self.assingment()?;
 
-            if let Expr::Variable(v) = *expr {
-                Ok(Box::new(Expr::Assign(
+            match *expr {
+                Expr::Variable(v) => Ok(Box::new(Expr::Assign(
                     Assign {
+                        span: v.span.to(value.span()),
                         name: v.name,
                         value,
+                        slot: Cell::new(None),
                     }
-                )))
-            } else {
-                Err(self.error(&equals, "Invalid assignment type."))
+                ))),
+                Expr::Get(get) => Ok(Box::new(Expr::Set(
+                    Set {
+                        span: get.span.to(value.span()),
+                        object: get.object,
+                        name: get.name,
+                        value,
+                    }
+                ))),
+                _ => Err(self.error(&equals, "Invalid assignment type.")),
             }
         } else {
             Ok(expr)
         }
     }
 
+    fn or(&mut self) -> Result<Box<Expr>, ParseError> {
+        let mut expr = self.and()?;
+
+        while self.match_token(&[TokenType::OR]) {
+            let operator = self.previous().clone();
+            let right = self.and()?;
+            expr = Box::new(Expr::Logical(
+                Logical {
+                    span: expr.span().to(right.span()),
+                    left: expr,
+                    operator,
+                    right,
+                }
+            ));
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Box<Expr>, ParseError> {
+        let mut expr = self.equality()?;
+
+        while self.match_token(&[TokenType::AND]) {
+            let operator = self.previous().clone();
+            let right = self.equality()?;
+            expr = Box::new(Expr::Logical(
+                Logical {
+                    span: expr.span().to(right.span()),
+                    left: expr,
+                    operator,
+                    right,
+                }
+            ));
+        }
+
+        Ok(expr)
+    }
 
     fn equality(&mut self) -> Result<Box<Expr>, ParseError> {
         let mut expr = self.comparison()?;
@@ -203,6 +607,7 @@ impl Parser {
             let right = self.comparison()?;
             expr = Box::new(Expr::Binary(
                 Binary {
+                    span: expr.span().to(right.span()),
                     left: expr,
                     operator,
                     right,
@@ -224,6 +629,7 @@ impl Parser {
             let right = self.term()?;
             expr = Box::new(Expr::Binary(
                 Binary {
+                    span: expr.span().to(right.span()),
                     left: expr,
                     operator,
                     right,
@@ -242,6 +648,7 @@ impl Parser {
             let right = self.factor()?;
             expr = Box::new(Expr::Binary(
                 Binary {
+                    span: expr.span().to(right.span()),
                     left: expr,
                     operator,
                     right,
@@ -260,6 +667,7 @@ impl Parser {
             let right = self.unary()?;
             expr = Box::new(Expr::Binary(
                 Binary {
+                    span: expr.span().to(right.span()),
                     left: expr,
                     operator,
                     right,
@@ -276,6 +684,7 @@ impl Parser {
             let right = self.unary()?;
             return Ok(Box::new(Expr::Unary(
                         Unary {
+                            span: operator.span().to(right.span()),
                             operator,
                             right
                         })
@@ -283,7 +692,59 @@ impl Parser {
                 );
         }
 
-        self.primary() 
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Box<Expr>, ParseError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.match_token(&[TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_token(&[TokenType::DOT]) {
+                let name = self.consume_identifier(
+                    "Expect property name after '.'."
+                )?;
+                let span = expr.span().to(name.span());
+                expr = Box::new(Expr::Get(Get { object: expr, name, span }));
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    // assumes the opening '(' has already been consumed
+    fn finish_call(&mut self,
+                   callee: Box<Expr>) -> Result<Box<Expr>, ParseError> {
+        let mut arguments = vec![];
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    self.error_at_current(&format!(
+                        "Can't have more than {} arguments.", MAX_ARGUMENTS
+                    ));
+                }
+                arguments.push(self.expression()?);
+
+                if !self.match_token(&[TokenType::COMMA]) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self.consume(&TokenType::RightParen,
+                                 "Expect ')' after arguments.")?.clone();
+
+        Ok(Box::new(Expr::Call(
+            Call {
+                span: callee.span().to(paren.span()),
+                callee,
+                paren,
+                arguments,
+            }
+        )))
     }
 
     fn primary(&mut self) -> Result<Box<Expr>, ParseError> {
@@ -292,25 +753,73 @@ impl Parser {
                               TokenType::NIL,
                              ]) {
             return Ok(Box::new(Expr::Literal(
-                    Literal { value: self.previous().clone() }
-                    )
+                    Literal {
+                        value: self.previous().clone(),
+                        span: self.previous().span(),
+                    })
                 ));
         }
 
+        if self.match_token(&[TokenType::THIS]) {
+            let keyword = self.previous().clone();
+            if self.current_class == ClassKind::None {
+                self.error(&keyword, "Can't use 'this' outside of a class.");
+            }
+            return Ok(Box::new(Expr::This(This {
+                span: keyword.span(),
+                keyword,
+                slot: Cell::new(None),
+            })));
+        }
+
+        if self.match_token(&[TokenType::SUPER]) {
+            let keyword = self.previous().clone();
+            match self.current_class {
+                ClassKind::None => {
+                    self.error(&keyword,
+                               "Can't use 'super' outside of a class.");
+                },
+                ClassKind::Class => {
+                    self.error(&keyword, "Can't use 'super' in a class with \
+                                          no superclass.");
+                },
+                ClassKind::Subclass => {},
+            }
+            self.consume(&TokenType::DOT, "Expect '.' after 'super'.")?;
+            let method = self.consume_identifier(
+                "Expect superclass method name."
+            )?;
+            return Ok(Box::new(Expr::Super(Super {
+                span: keyword.span().to(method.span()),
+                keyword,
+                method,
+                slot: Cell::new(None),
+            })));
+        }
+
         match &self.peek().token_type {
             TokenType::LITERAL(token_type::Literal::NUMBER(_))
             | TokenType::LITERAL(token_type::Literal::STRING(_))
             => {
                 self.advance();
                 Ok(Box::new(Expr::Literal(
-                    Literal { value: self.previous().clone() }
+                    Literal {
+                        value: self.previous().clone(),
+                        span: self.previous().span(),
+                    }
                 )))
             },
+            TokenType::LITERAL(token_type::Literal::INTERPOLATION(_))
+            => self.interpolation(),
             TokenType::LITERAL(token_type::Literal::IDENTIFIER(_))
             => {
                 self.advance();
                 Ok(Box::new(Expr::Variable(
-                    Variable { name: self.previous().clone() }
+                    Variable {
+                        name: self.previous().clone(),
+                        slot: Cell::new(None),
+                        span: self.previous().span(),
+                    }
                 )))
             }
             _ => {
@@ -322,9 +831,61 @@ impl Parser {
                     return Ok(expr);
                 }
 
-                Err(self.error(self.peek(), "Expect expression."))
+                Err(self.error_at_current("Expect expression."))
             }
         }
     }
+
+    // The scanner splits `"a ${b} c ${d} e"` into INTERPOLATION("a ") b
+    // InterpolationMiddle(" c ") d InterpolationEnd(" e"), every segment but
+    // the last is followed by an expression.
+    fn interpolation(&mut self) -> Result<Box<Expr>, ParseError> {
+        let start = self.peek().span();
+        let mut parts = vec![];
+
+        loop {
+            let mut segment = self.advance().clone();
+            let (value, last) = match segment.token_type {
+                TokenType::LITERAL(token_type::Literal::INTERPOLATION(s))
+                | TokenType::LITERAL(
+                    token_type::Literal::InterpolationMiddle(s)
+                ) => (s, false),
+                TokenType::LITERAL(token_type::Literal::InterpolationEnd(s))
+                => (s, true),
+                _ => unreachable!("segments follow an interpolation"),
+            };
+
+            // empty segments, e.g. around `${a}${b}`, add nothing
+            if !value.is_empty() {
+                segment.token_type =
+                    TokenType::LITERAL(token_type::Literal::STRING(value));
+                parts.push(Box::new(Expr::Literal(Literal {
+                    span: segment.span(),
+                    value: segment,
+                })));
+            }
+            if last {
+                break;
+            }
+
+            parts.push(self.expression()?);
+
+            match &self.peek().token_type {
+                TokenType::LITERAL(
+                    token_type::Literal::InterpolationMiddle(_)
+                )
+                | TokenType::LITERAL(token_type::Literal::InterpolationEnd(_))
+                => {},
+                _ => return Err(self.error_at_current(
+                    "Expect '}' after expression in string interpolation."
+                )),
+            }
+        }
+
+        Ok(Box::new(Expr::Interpolation(Interpolation {
+            parts,
+            span: self.span_from(start),
+        })))
+    }
 }
 
@@ -0,0 +1,134 @@
+// Meta-commands of the REPL, a line starting with `:` is never valid Lox so
+// they can't be mistaken for code.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    // runs a script in the current globals
+    Load(String),
+    // lists the globals and their values
+    Env,
+    // drops every global
+    Reset,
+    // shows what the parser makes of some code
+    Ast(String),
+    // shows what the scanner makes of some code
+    Tokens(String),
+    // runs some code and shows how long it took
+    Time(String),
+    Help,
+}
+
+pub const HELP: &str = "\
+:load <file>   run a script, keeping what it defines
+:env           list the global variables
+:reset         forget every global variable
+:ast <code>    print the syntax tree of some code
+:tokens <code> print the tokens of some code
+:time <code>   run some code and print how long it took
+:help          print this message";
+
+impl Command {
+    // `None` when the line isn't a command, an error message when it's a
+    // command used the wrong way
+    pub fn parse(line: &str) -> Option<Result<Command, String>> {
+        let line = line.trim().strip_prefix(':')?;
+        let (name, argument) = match line.find(char::is_whitespace) {
+            Some(end) => (&line[..end], line[end..].trim()),
+            None => (line, ""),
+        };
+
+        let command = match (name, argument) {
+            ("env", "") => Command::Env,
+            ("reset", "") => Command::Reset,
+            ("help", "") => Command::Help,
+            ("env", _) | ("reset", _) | ("help", _) => {
+                return Some(Err(format!("Usage: :{}", name)));
+            },
+
+            ("load", "") => {
+                return Some(Err(String::from("Usage: :load <file>")));
+            },
+            ("load", file) => Command::Load(file.to_string()),
+
+            ("ast", "") | ("tokens", "") | ("time", "") => {
+                return Some(Err(format!("Usage: :{} <code>", name)));
+            },
+            ("ast", code) => Command::Ast(code.to_string()),
+            ("tokens", code) => Command::Tokens(code.to_string()),
+            ("time", code) => Command::Time(code.to_string()),
+
+            _ => {
+                return Some(Err(format!(
+                    "Unknown command ':{}', try ':help'.", name,
+                )));
+            },
+        };
+        Some(Ok(command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<Result<Command, String>> {
+        Command::parse(line)
+    }
+
+    #[test]
+    fn code_is_not_a_command() {
+        assert_eq!(parse("print 1;"), None);
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("a :env"), None);
+    }
+
+    #[test]
+    fn parses_commands_without_arguments() {
+        assert_eq!(parse(":env"), Some(Ok(Command::Env)));
+        assert_eq!(parse(":reset"), Some(Ok(Command::Reset)));
+        assert_eq!(parse(":help"), Some(Ok(Command::Help)));
+    }
+
+    #[test]
+    fn parses_commands_with_arguments() {
+        assert_eq!(parse(":load script.lox"),
+                   Some(Ok(Command::Load(String::from("script.lox")))));
+        assert_eq!(parse(":ast 1 + 2"),
+                   Some(Ok(Command::Ast(String::from("1 + 2")))));
+        assert_eq!(parse(":tokens var a;"),
+                   Some(Ok(Command::Tokens(String::from("var a;")))));
+        assert_eq!(parse(":time fib(20)"),
+                   Some(Ok(Command::Time(String::from("fib(20)")))));
+    }
+
+    #[test]
+    fn surrounding_whitespace_is_ignored() {
+        assert_eq!(parse("  :env  "), Some(Ok(Command::Env)));
+        assert_eq!(parse(":load \t dir/my script.lox \n"),
+                   Some(Ok(Command::Load(String::from("dir/my script.lox")))));
+        assert_eq!(parse(":ast\t1"), Some(Ok(Command::Ast(String::from("1")))));
+    }
+
+    #[test]
+    fn reports_usage_errors() {
+        assert_eq!(parse(":env x"), Some(Err(String::from("Usage: :env"))));
+        assert_eq!(parse(":help me"), Some(Err(String::from("Usage: :help"))));
+        assert_eq!(parse(":load"),
+                   Some(Err(String::from("Usage: :load <file>"))));
+        assert_eq!(parse(":time   "),
+                   Some(Err(String::from("Usage: :time <code>"))));
+    }
+
+    #[test]
+    fn reports_unknown_commands() {
+        assert_eq!(parse(":quit"), Some(Err(String::from(
+            "Unknown command ':quit', try ':help'."
+        ))));
+        assert_eq!(parse(":"), Some(Err(String::from(
+            "Unknown command ':', try ':help'."
+        ))));
+        // commands are case sensitive
+        assert_eq!(parse(":ENV"), Some(Err(String::from(
+            "Unknown command ':ENV', try ':help'."
+        ))));
+    }
+}
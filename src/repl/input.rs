@@ -0,0 +1,96 @@
+use crate::ast::node::Expr;
+use crate::diagnostic::diagnostic::Diagnostics;
+use crate::lexer::scanner::Scanner;
+use crate::parser::parser::Parser;
+
+// Whether the REPL should ask for another line before running the input.
+// That's the case when every error found by scanning and parsing it could be
+// fixed by typing more, i.e. unbalanced parentheses or braces, a statement
+// cut short or a string that isn't closed yet.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut diagnostics = Diagnostics::new();
+
+    let tokens = Scanner::new(source.to_string(), &mut diagnostics)
+        .scan_tokens();
+    if diagnostics.is_empty() {
+        Parser::new(tokens, &mut diagnostics).parse();
+    }
+
+    !diagnostics.is_empty()
+        && diagnostics.iter().all(|diagnostic| diagnostic.incomplete)
+}
+
+// the input as a bare expression, e.g. `1 + 2`, whose value the REPL echoes.
+// Errors are dropped, the input is then run as statements and reports them.
+pub fn parse_expression(source: &str) -> Option<Box<Expr>> {
+    let mut diagnostics = Diagnostics::new();
+
+    let tokens = Scanner::new(source.to_string(), &mut diagnostics)
+        .scan_tokens();
+    if !diagnostics.is_empty() {
+        return None;
+    }
+
+    let expression = Parser::new(tokens, &mut diagnostics).parse_expression();
+    if !diagnostics.is_empty() {
+        return None;
+    }
+    expression
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ast_printer::AstPrinter;
+
+    #[test]
+    fn complete_input_runs() {
+        assert!(!is_incomplete("print 1;\n"));
+        assert!(!is_incomplete("fun f() {\n  return 1;\n}\n"));
+        assert!(!is_incomplete(""));
+    }
+
+    #[test]
+    fn unfinished_input_waits_for_more() {
+        assert!(is_incomplete("fun f() {\n"));
+        assert!(is_incomplete("print (1 +\n"));
+        assert!(is_incomplete("print 1\n"));
+        assert!(is_incomplete("print \"one\ntwo\n"));
+        assert!(is_incomplete("print \"\"\"\n  text\n"));
+        assert!(is_incomplete("print \"${1 +\n"));
+        assert!(is_incomplete("/* a /* nested */\n"));
+    }
+
+    #[test]
+    fn errors_more_input_cant_fix_run_anyway() {
+        assert!(!is_incomplete("print );\n"));
+        assert!(!is_incomplete("var = 1;\n"));
+        assert!(!is_incomplete("@\n"));
+        // a later line can't close a string after an invalid escape
+        assert!(!is_incomplete("print \"\\q\n"));
+        assert!(!is_incomplete("fun f() {\nprint );\n"));
+    }
+
+    fn expression(source: &str) -> Option<String> {
+        parse_expression(source)
+            .map(|expression| AstPrinter::new().print_expr(&expression))
+    }
+
+    #[test]
+    fn bare_expressions_are_parsed() {
+        assert_eq!(expression("1 + 2"), Some(String::from("(+ 1 2)")));
+        assert_eq!(expression("1 + 2;\n"), Some(String::from("(+ 1 2)")));
+        assert_eq!(expression("a = f(1)"),
+                   Some(String::from("(= a (call f 1))")));
+    }
+
+    #[test]
+    fn statements_are_not_expressions() {
+        assert_eq!(expression("print 1;"), None);
+        assert_eq!(expression("var a = 1;"), None);
+        assert_eq!(expression("1; 2;"), None);
+        assert_eq!(expression("1 +"), None);
+        assert_eq!(expression("\"open"), None);
+        assert_eq!(expression(""), None);
+    }
+}
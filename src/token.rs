@@ -1,4 +1,4 @@
+pub mod span;
 pub mod token_type;
 pub mod token;
 pub mod value;
-
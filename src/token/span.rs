@@ -0,0 +1,17 @@
+// Region of the source text. Offsets are in bytes with `end` exclusive, so
+// `&source[span.start..span.end]` is the spanned text, line and column are
+// those of its first character, columns count characters starting at 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Span {
+    // smallest span covering both this span and `end`, which comes after it
+    pub fn to(self, end: Span) -> Span {
+        Span { end: end.end.max(self.end), ..self }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::token::span::Span;
 use crate::token::token_type::TokenType;
 
 #[derive(Clone, Debug)]
@@ -5,5 +6,19 @@ pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub line: u32,
+    pub column: u32,
+    // byte offsets of the token in the source, `end` is exclusive
+    pub start: usize,
+    pub end: usize,
 }
 
+impl Token {
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.start,
+            end: self.end,
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
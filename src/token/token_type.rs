@@ -21,7 +21,7 @@ pub enum TokenType {
     LITERAL(Literal), 
 
     // Keywords.
-    AND, CLASS, ELSE, FALSE, FUN, FOR, IF, NIL, OR,
+    AND, BREAK, CLASS, CONTINUE, ELSE, FALSE, FUN, FOR, IF, NIL, OR,
     PRINT, RETURN, SUPER, THIS, TRUE, VAR, WHILE,
 
     EOF
@@ -32,6 +32,14 @@ pub enum Literal {
     IDENTIFIER(String),
     STRING(String),
     NUMBER(f64),
+    // part of a string up to a `${`, the rest of the string comes after the
+    // interpolated expression
+    INTERPOLATION(String),
+    // part of a string between the `}` closing an interpolation and the
+    // next `${`
+    InterpolationMiddle(String),
+    // rest of a string after the `}` closing its last interpolation
+    InterpolationEnd(String),
 }
 
 impl std::fmt::Display for TokenType {
@@ -46,7 +54,9 @@ impl std::fmt::Display for TokenType {
 pub fn get_token_type(literal: String) -> TokenType {
     match &literal[..] {
         "and" => TokenType::AND,
+        "break" => TokenType::BREAK,
         "class" =>TokenType::CLASS,
+        "continue" => TokenType::CONTINUE,
         "else" => TokenType::ELSE,
         "false" => TokenType::FALSE,
         "fun" => TokenType::FUN,
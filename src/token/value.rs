@@ -1,6 +1,8 @@
+use crate::interpreter::callable::{Class, Function, Instance, NativeFunction};
 use crate::token::token_type::{TokenType, Literal};
 
 use std::fmt;
+use std::rc::Rc;
 
 // https://github.com/brightly-salty/rox/blob/master/src/value.rs
 // The idea of Value apart from using Box<dyn Any> is good
@@ -10,6 +12,10 @@ pub enum Value {
     String(String),
     Number(f64),
     Bool(bool),
+    Function(Rc<Function>),
+    NativeFunction(Rc<NativeFunction>),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
     Nil
 }
 
@@ -30,6 +36,11 @@ impl fmt::Display for Value {
                 )
             },
             Value::Bool(b) => write!(f, "{}", b),
+            Value::Function(fun) => write!(f, "<fn {}>", fun.name.lexeme),
+            Value::NativeFunction(_) => write!(f, "<native fn>"),
+            Value::Class(class) => write!(f, "{}", class.name),
+            Value::Instance(instance) =>
+                write!(f, "{} instance", instance.class.name),
             Value::Nil => write!(f, "nil"),
 
         }
@@ -0,0 +1,5 @@
+pub mod chunk;
+pub mod compiler;
+pub mod heap;
+pub mod value;
+pub mod vm;
@@ -0,0 +1,110 @@
+use super::value::Value;
+
+// Operands follow the opcode in the byte stream: constant, global and
+// property operands are two byte indices into the constant pool, local and
+// upvalue operands are one byte stack/upvalue slots and jumps are two byte
+// unsigned offsets.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpCode {
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    GetUpvalue,
+    SetUpvalue,
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    // followed by the function constant and then a pair of bytes
+    // (is_local, index) for each upvalue it captures
+    Closure,
+    CloseUpvalue,
+    Return,
+    Class,
+    Inherit,
+    Method,
+    // followed by the number of parts to pop and concatenate
+    Interpolate,
+}
+
+impl OpCode {
+    // every opcode in the order of its discriminant
+    const ALL: [OpCode; 39] = [
+        OpCode::Constant, OpCode::Nil, OpCode::True, OpCode::False,
+        OpCode::Pop, OpCode::GetLocal, OpCode::SetLocal, OpCode::GetGlobal,
+        OpCode::DefineGlobal, OpCode::SetGlobal, OpCode::GetUpvalue,
+        OpCode::SetUpvalue, OpCode::GetProperty, OpCode::SetProperty,
+        OpCode::GetSuper, OpCode::Equal, OpCode::NotEqual, OpCode::Greater,
+        OpCode::GreaterEqual, OpCode::Less, OpCode::LessEqual, OpCode::Add,
+        OpCode::Subtract, OpCode::Multiply, OpCode::Divide, OpCode::Not,
+        OpCode::Negate, OpCode::Print, OpCode::Jump, OpCode::JumpIfFalse,
+        OpCode::Loop, OpCode::Call, OpCode::Closure, OpCode::CloseUpvalue,
+        OpCode::Return, OpCode::Class, OpCode::Inherit, OpCode::Method,
+        OpCode::Interpolate,
+    ];
+
+    pub fn from_byte(byte: u8) -> OpCode {
+        OpCode::ALL[byte as usize]
+    }
+}
+
+// first instruction offset emitted for a given source line
+struct LineStart {
+    offset: usize,
+    line: u32,
+}
+
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    // run-length encoded, a new entry is only added when the line changes
+    lines: Vec<LineStart>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Chunk::default()
+    }
+
+    pub fn write(&mut self, byte: u8, line: u32) {
+        if self.lines.last().is_none_or(|last| last.line != line) {
+            self.lines.push(LineStart { offset: self.code.len(), line });
+        }
+        self.code.push(byte);
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    pub fn line(&self, offset: usize) -> u32 {
+        let index = self.lines.partition_point(|start| start.offset <= offset);
+        self.lines[index.saturating_sub(1)].line
+    }
+}
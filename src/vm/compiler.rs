@@ -0,0 +1,726 @@
+use crate::ast::node::*;
+use crate::diagnostic::diagnostic::{Code, Diagnostic, Diagnostics};
+use crate::token::token::Token;
+use crate::token::token_type::TokenType;
+use crate::token::value;
+
+use super::chunk::OpCode;
+use super::heap::{Handle, Heap};
+use super::value::{FunctionProto, Obj, Value};
+
+// operands of local and upvalue instructions are a single byte
+const MAX_LOCALS: usize = 256;
+const MAX_UPVALUES: usize = 256;
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+struct Local {
+    name: String,
+    // `None` while the initializer of the variable is being compiled
+    depth: Option<usize>,
+    // captured locals are moved to the heap when they go out of scope
+    is_captured: bool,
+}
+
+struct UpvalueRef {
+    index: u8,
+    // whether it captures a local of the enclosing function or one of its
+    // upvalues
+    is_local: bool,
+}
+
+struct LoopState {
+    // scope depth outside of the loop body, locals deeper than this are
+    // discarded by `break` and `continue`
+    scope_depth: usize,
+    break_jumps: Vec<usize>,
+    continue_jumps: Vec<usize>,
+}
+
+// compilation state of the function currently being compiled, one per
+// level of nesting
+struct FunctionState {
+    function: FunctionProto,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+    loops: Vec<LoopState>,
+}
+
+impl FunctionState {
+    fn new(name: String, kind: FunctionKind) -> Self {
+        // slot zero holds the callee, methods see it as `this`
+        let slot_zero = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            _ => "",
+        };
+
+        FunctionState {
+            function: FunctionProto { name, ..FunctionProto::default() },
+            kind,
+            locals: vec![Local {
+                name: String::from(slot_zero),
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: vec![],
+            scope_depth: 0,
+            loops: vec![],
+        }
+    }
+}
+
+// Compiles resolved statements to bytecode. Each top-level statement becomes
+// its own script function so the virtual machine can go on with the next one
+// after a runtime error, the same way `Interpreter::interpret` does.
+// Function protos and string constants are allocated on the heap of the
+// virtual machine that runs them.
+pub struct Compiler<'a> {
+    heap: &'a mut Heap,
+    diagnostics: &'a mut Diagnostics,
+    states: Vec<FunctionState>,
+    // line of the token the next instructions are emitted for
+    line: u32,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(heap: &'a mut Heap, diagnostics: &'a mut Diagnostics) -> Self {
+        Compiler { heap, diagnostics, states: vec![], line: 0 }
+    }
+
+    pub fn compile(&mut self, stmts: &[Stmt]) -> Vec<Handle> {
+        let mut scripts = vec![];
+        for stmt in stmts {
+            self.states.push(FunctionState::new(String::new(),
+                                                FunctionKind::Script));
+            stmt.accept(self);
+            self.emit_return();
+            let state = self.states.pop().unwrap();
+            scripts.push(self.heap.alloc(Obj::Function(state.function)));
+        }
+        scripts
+    }
+
+    fn compile_expr(&mut self, expr: &Expr) {
+        expr.accept(self)
+    }
+
+    fn state(&mut self) -> &mut FunctionState {
+        self.states.last_mut().unwrap()
+    }
+
+    fn current_offset(&mut self) -> usize {
+        self.state().function.chunk.code.len()
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let line = self.line;
+        self.state().function.chunk.write(byte, line);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        self.emit_byte((value >> 8) as u8);
+        self.emit_byte(value as u8);
+    }
+
+    fn emit_op_u16(&mut self, op: OpCode, operand: u16) {
+        self.emit_op(op);
+        self.emit_u16(operand);
+    }
+
+    fn emit_return(&mut self) {
+        if self.state().kind == FunctionKind::Initializer {
+            self.emit_op(OpCode::GetLocal);
+            self.emit_byte(0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.emit_op(OpCode::Return);
+    }
+
+    fn error(&mut self, line: u32, message: &str) {
+        self.diagnostics.report(
+            Diagnostic::error_at_line(Code::Compile, line, message.to_string())
+        );
+    }
+
+    fn make_constant(&mut self, value: Value) -> u16 {
+        // strings are interned, so names used several times share an entry
+        if let Value::Obj(_) = value {
+            let constants = &self.state().function.chunk.constants;
+            if let Some(index) = constants.iter().position(|c| *c == value) {
+                return index as u16;
+            }
+        }
+
+        let index = self.state().function.chunk.add_constant(value);
+        if index > u16::MAX as usize {
+            self.error(self.line, "Too many constants in one chunk.");
+            return 0;
+        }
+        index as u16
+    }
+
+    fn identifier_constant(&mut self, name: &Token) -> u16 {
+        let name = self.heap.intern(&name.lexeme);
+        self.make_constant(Value::Obj(name))
+    }
+
+    // returns the offset of the operand to patch once the target is known
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_u16(u16::MAX);
+        self.current_offset() - 2
+    }
+
+    fn patch_jump(&mut self, operand: usize) {
+        let jump = self.current_offset() - operand - 2;
+        if jump > u16::MAX as usize {
+            self.error(self.line, "Too much code to jump over.");
+        }
+
+        let code = &mut self.state().function.chunk.code;
+        code[operand] = (jump >> 8) as u8;
+        code[operand + 1] = jump as u8;
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit_op(OpCode::Loop);
+
+        // also jumps back over the operand itself
+        let offset = self.current_offset() - loop_start + 2;
+        if offset > u16::MAX as usize {
+            self.error(self.line, "Loop body too large.");
+        }
+        self.emit_u16(offset as u16);
+    }
+
+    fn begin_scope(&mut self) {
+        self.state().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.state().scope_depth -= 1;
+
+        loop {
+            let state = self.state();
+            let captured = match state.locals.last() {
+                Some(local) if local.depth > Some(state.scope_depth) =>
+                    local.is_captured,
+                _ => break,
+            };
+            state.locals.pop();
+
+            self.emit_op(if captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            });
+        }
+    }
+
+    // emits the pops for the locals declared inside the innermost loop
+    // without forgetting them, used when jumping out of the loop body
+    fn discard_loop_locals(&mut self) {
+        let state = self.state();
+        let depth = state.loops.last().unwrap().scope_depth;
+        let ops: Vec<OpCode> = state.locals.iter().rev()
+            .take_while(|local| local.depth > Some(depth))
+            .map(|local| if local.is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            })
+            .collect();
+
+        for op in ops {
+            self.emit_op(op);
+        }
+    }
+
+    fn add_local(&mut self, name: &Token) {
+        if self.state().locals.len() == MAX_LOCALS {
+            self.error(name.line, "Too many local variables in function.");
+            return;
+        }
+
+        self.state().locals.push(Local {
+            name: name.lexeme.clone(),
+            depth: None,
+            is_captured: false,
+        });
+    }
+
+    fn mark_initialized(&mut self) {
+        let state = self.state();
+        let depth = state.scope_depth;
+        if let Some(local) = state.locals.last_mut() {
+            local.depth = Some(depth);
+        }
+    }
+
+    // declares a local in the current scope, globals are late bound and
+    // don't need to be declared
+    fn declare_variable(&mut self, name: &Token) {
+        if self.state().scope_depth > 0 {
+            self.add_local(name);
+        }
+    }
+
+    fn define_variable(&mut self, name: &Token) {
+        if self.state().scope_depth > 0 {
+            self.mark_initialized();
+        } else {
+            let global = self.identifier_constant(name);
+            self.line = name.line;
+            self.emit_op_u16(OpCode::DefineGlobal, global);
+        }
+    }
+
+    fn resolve_local(&self, state: usize, name: &str) -> Option<u8> {
+        self.states[state].locals.iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8)
+    }
+
+    fn add_upvalue(&mut self, state: usize, index: u8, is_local: bool) -> u8 {
+        let upvalues = &mut self.states[state].upvalues;
+        if let Some(existing) = upvalues.iter()
+            .position(|u| u.index == index && u.is_local == is_local) {
+            return existing as u8;
+        }
+
+        if upvalues.len() == MAX_UPVALUES {
+            self.error(self.line, "Too many closure variables in function.");
+            return 0;
+        }
+
+        upvalues.push(UpvalueRef { index, is_local });
+        let count = upvalues.len();
+        self.states[state].function.upvalue_count = count;
+        (count - 1) as u8
+    }
+
+    fn resolve_upvalue(&mut self, state: usize, name: &str) -> Option<u8> {
+        if state == 0 {
+            return None;
+        }
+
+        if let Some(local) = self.resolve_local(state - 1, name) {
+            self.states[state - 1].locals[local as usize].is_captured = true;
+            return Some(self.add_upvalue(state, local, true));
+        }
+
+        let upvalue = self.resolve_upvalue(state - 1, name)?;
+        Some(self.add_upvalue(state, upvalue, false))
+    }
+
+    // reads the variable, or assigns `value` to it when one is given
+    fn named_variable(&mut self, name: &Token, value: Option<&Expr>) {
+        let state = self.states.len() - 1;
+
+        if let Some(slot) = self.resolve_local(state, &name.lexeme) {
+            self.emit_variable_op(value, OpCode::GetLocal, OpCode::SetLocal);
+            self.emit_byte(slot);
+        } else if let Some(slot) = self.resolve_upvalue(state, &name.lexeme) {
+            self.emit_variable_op(value, OpCode::GetUpvalue,
+                                  OpCode::SetUpvalue);
+            self.emit_byte(slot);
+        } else {
+            let global = self.identifier_constant(name);
+            self.emit_variable_op(value, OpCode::GetGlobal,
+                                  OpCode::SetGlobal);
+            self.emit_u16(global);
+        }
+    }
+
+    fn emit_variable_op(&mut self,
+                        value: Option<&Expr>,
+                        get: OpCode,
+                        set: OpCode) {
+        let line = self.line;
+        match value {
+            Some(value) => {
+                self.compile_expr(value);
+                self.line = line;
+                self.emit_op(set);
+            },
+            None => self.emit_op(get),
+        }
+    }
+
+    fn function(&mut self, function: &Function, kind: FunctionKind) {
+        self.states.push(FunctionState::new(function.name.lexeme.clone(),
+                                            kind));
+        self.begin_scope();
+
+        self.state().function.arity = function.params.len();
+        for param in &function.params {
+            self.declare_variable(param);
+            self.define_variable(param);
+        }
+
+        for stmt in function.body.iter() {
+            stmt.accept(self);
+        }
+        self.emit_return();
+
+        // no need to close the scope, returning discards the whole frame
+        let state = self.states.pop().unwrap();
+        let proto = self.heap.alloc(Obj::Function(state.function));
+        let constant = self.make_constant(Value::Obj(proto));
+
+        self.line = function.name.line;
+        self.emit_op_u16(OpCode::Closure, constant);
+        for upvalue in state.upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_byte(upvalue.index);
+        }
+    }
+}
+
+impl Visitor for Compiler<'_> {
+    type R = ();
+
+    fn visit_assign (&mut self, assign: &Assign) -> Self::R {
+        self.line = assign.name.line;
+        self.named_variable(&assign.name, Some(&assign.value));
+    }
+
+    fn visit_binary (&mut self, binary: &Binary) -> Self::R {
+        self.compile_expr(&binary.left);
+        self.compile_expr(&binary.right);
+
+        self.line = binary.operator.line;
+        self.emit_op(match binary.operator.token_type {
+            TokenType::BangEqual => OpCode::NotEqual,
+            TokenType::EqualEqual => OpCode::Equal,
+            TokenType::GREATER => OpCode::Greater,
+            TokenType::GreaterEqual => OpCode::GreaterEqual,
+            TokenType::LESS => OpCode::Less,
+            TokenType::LessEqual => OpCode::LessEqual,
+            TokenType::MINUS => OpCode::Subtract,
+            TokenType::PLUS => OpCode::Add,
+            TokenType::SLASH => OpCode::Divide,
+            TokenType::STAR => OpCode::Multiply,
+            _ => unreachable!("the parser only builds binary arithmetic, \
+                               comparison and equality operators"),
+        });
+    }
+
+    fn visit_call (&mut self, call: &Call) -> Self::R {
+        self.compile_expr(&call.callee);
+        for argument in &call.arguments {
+            self.compile_expr(argument);
+        }
+
+        self.line = call.paren.line;
+        self.emit_op(OpCode::Call);
+        self.emit_byte(call.arguments.len() as u8);
+    }
+
+    fn visit_get (&mut self, get: &Get) -> Self::R {
+        self.compile_expr(&get.object);
+
+        let name = self.identifier_constant(&get.name);
+        self.line = get.name.line;
+        self.emit_op_u16(OpCode::GetProperty, name);
+    }
+
+    fn visit_grouping (&mut self, grouping: &Grouping) -> Self::R {
+        self.compile_expr(&grouping.expression);
+    }
+
+    fn visit_interpolation (&mut self, interpolation: &Interpolation)
+                            -> Self::R {
+        for part in &interpolation.parts {
+            self.compile_expr(part);
+        }
+
+        self.line = interpolation.span.line;
+        if interpolation.parts.len() > u8::MAX as usize {
+            self.error(self.line, "Too many parts in string interpolation.");
+        }
+        self.emit_op(OpCode::Interpolate);
+        self.emit_byte(interpolation.parts.len() as u8);
+    }
+
+    fn visit_literal (&mut self, literal: &Literal) -> Self::R {
+        self.line = literal.value.line;
+
+        match value::Value::from(literal.value.token_type.clone()) {
+            value::Value::Bool(true) => self.emit_op(OpCode::True),
+            value::Value::Bool(false) => self.emit_op(OpCode::False),
+            value::Value::Number(n) => {
+                let constant = self.make_constant(Value::Number(n));
+                self.emit_op_u16(OpCode::Constant, constant);
+            },
+            value::Value::String(s) => {
+                let string = self.heap.intern(&s);
+                let constant = self.make_constant(Value::Obj(string));
+                self.emit_op_u16(OpCode::Constant, constant);
+            },
+            _ => self.emit_op(OpCode::Nil),
+        }
+    }
+
+    fn visit_logical (&mut self, logical: &Logical) -> Self::R {
+        self.compile_expr(&logical.left);
+        self.line = logical.operator.line;
+
+        // the jumps leave the left operand on the stack as the result
+        if logical.operator.token_type == TokenType::OR {
+            let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+            let end_jump = self.emit_jump(OpCode::Jump);
+            self.patch_jump(else_jump);
+            self.emit_op(OpCode::Pop);
+            self.compile_expr(&logical.right);
+            self.patch_jump(end_jump);
+        } else {
+            let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+            self.emit_op(OpCode::Pop);
+            self.compile_expr(&logical.right);
+            self.patch_jump(end_jump);
+        }
+    }
+
+    fn visit_set (&mut self, set: &Set) -> Self::R {
+        self.compile_expr(&set.object);
+        self.compile_expr(&set.value);
+
+        let name = self.identifier_constant(&set.name);
+        self.line = set.name.line;
+        self.emit_op_u16(OpCode::SetProperty, name);
+    }
+
+    fn visit_super (&mut self, super_: &Super) -> Self::R {
+        self.line = super_.keyword.line;
+        let this = Token {
+            lexeme: String::from("this"),
+            ..super_.keyword.clone()
+        };
+        self.named_variable(&this, None);
+        self.named_variable(&super_.keyword, None);
+
+        let method = self.identifier_constant(&super_.method);
+        self.line = super_.method.line;
+        self.emit_op_u16(OpCode::GetSuper, method);
+    }
+
+    fn visit_this (&mut self, this: &This) -> Self::R {
+        self.line = this.keyword.line;
+        self.named_variable(&this.keyword, None);
+    }
+
+    fn visit_unary (&mut self, unary: &Unary) -> Self::R {
+        self.compile_expr(&unary.right);
+
+        self.line = unary.operator.line;
+        self.emit_op(match unary.operator.token_type {
+            TokenType::MINUS => OpCode::Negate,
+            _ => OpCode::Not,
+        });
+    }
+
+    fn visit_variable (&mut self, variable: &Variable) -> Self::R {
+        self.line = variable.name.line;
+        self.named_variable(&variable.name, None);
+    }
+}
+
+impl StmtVisitor for Compiler<'_> {
+    type R = ();
+
+    fn visit_expression (&mut self, expression: &Expression) -> Self::R {
+        self.compile_expr(&expression.expression);
+        self.emit_op(OpCode::Pop);
+    }
+
+    fn visit_print (&mut self, print: &Print) -> Self::R {
+        self.compile_expr(&print.expression);
+        self.emit_op(OpCode::Print);
+    }
+
+    fn visit_var (&mut self, var: &Var) -> Self::R {
+        self.declare_variable(&var.name);
+
+        match &var.initializer {
+            Some(initializer) => self.compile_expr(initializer),
+            None => {
+                self.line = var.name.line;
+                self.emit_op(OpCode::Nil);
+            },
+        }
+
+        self.define_variable(&var.name);
+    }
+
+    fn visit_block (&mut self, block: &Block) -> Self::R {
+        self.begin_scope();
+        for stmt in &block.statements {
+            stmt.accept(self);
+        }
+        self.end_scope();
+    }
+
+    fn visit_if (&mut self, if_: &If) -> Self::R {
+        self.compile_expr(&if_.condition);
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        if_.then_branch.accept(self);
+
+        let else_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(then_jump);
+        self.emit_op(OpCode::Pop);
+        if let Some(else_branch) = &if_.else_branch {
+            else_branch.accept(self);
+        }
+        self.patch_jump(else_jump);
+    }
+
+    fn visit_while (&mut self, while_: &While) -> Self::R {
+        let loop_start = self.current_offset();
+        self.compile_expr(&while_.condition);
+
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+
+        let scope_depth = self.state().scope_depth;
+        self.state().loops.push(LoopState {
+            scope_depth,
+            break_jumps: vec![],
+            continue_jumps: vec![],
+        });
+        while_.body.accept(self);
+        let state = self.state().loops.pop().unwrap();
+
+        for jump in state.continue_jumps {
+            self.patch_jump(jump);
+        }
+        if let Some(increment) = &while_.increment {
+            self.compile_expr(increment);
+            self.emit_op(OpCode::Pop);
+        }
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+        self.emit_op(OpCode::Pop);
+
+        // the condition has already been popped when breaking from the body
+        for jump in state.break_jumps {
+            self.patch_jump(jump);
+        }
+    }
+
+    fn visit_break (&mut self, break_: &Break) -> Self::R {
+        self.line = break_.keyword.line;
+        self.discard_loop_locals();
+        let jump = self.emit_jump(OpCode::Jump);
+        self.state().loops.last_mut().unwrap().break_jumps.push(jump);
+    }
+
+    fn visit_continue (&mut self, continue_: &Continue) -> Self::R {
+        self.line = continue_.keyword.line;
+        self.discard_loop_locals();
+        let jump = self.emit_jump(OpCode::Jump);
+        self.state().loops.last_mut().unwrap().continue_jumps.push(jump);
+    }
+
+    fn visit_function (&mut self, function: &Function) -> Self::R {
+        // initialized right away so the function can call itself
+        self.declare_variable(&function.name);
+        if self.state().scope_depth > 0 {
+            self.mark_initialized();
+        }
+
+        self.function(function, FunctionKind::Function);
+        self.define_variable(&function.name);
+    }
+
+    fn visit_return (&mut self, return_: &Return) -> Self::R {
+        self.line = return_.keyword.line;
+
+        match &return_.value {
+            Some(value) => {
+                self.compile_expr(value);
+                self.emit_op(OpCode::Return);
+            },
+            None => self.emit_return(),
+        }
+    }
+
+    fn visit_class (&mut self, class: &Class) -> Self::R {
+        self.line = class.name.line;
+        let name = self.identifier_constant(&class.name);
+
+        // a local class gets its slot up front, below the `super` scope
+        let local = self.state().scope_depth > 0;
+        if local {
+            self.emit_op(OpCode::Nil);
+            self.declare_variable(&class.name);
+            self.mark_initialized();
+        }
+
+        if let Some(superclass) = &class.superclass {
+            self.begin_scope();
+            self.compile_expr(superclass);
+            let super_token = Token {
+                token_type: TokenType::SUPER,
+                lexeme: String::from("super"),
+                ..class.name.clone()
+            };
+            self.add_local(&super_token);
+            self.mark_initialized();
+        }
+
+        self.line = class.name.line;
+        self.emit_op_u16(OpCode::Class, name);
+
+        if let Some(superclass) = &class.superclass {
+            if let Expr::Variable(variable) = &**superclass {
+                self.line = variable.name.line;
+            }
+            self.emit_op(OpCode::Inherit);
+        }
+
+        for method in &class.methods {
+            let kind = if method.name.lexeme == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            self.function(method, kind);
+
+            let method_name = self.identifier_constant(&method.name);
+            self.emit_op_u16(OpCode::Method, method_name);
+        }
+
+        self.line = class.name.line;
+        if local {
+            // the class is in its own slot, the copy on top isn't needed
+            let slot = self.resolve_local(self.states.len() - 1,
+                                          &class.name.lexeme).unwrap();
+            self.emit_op(OpCode::SetLocal);
+            self.emit_byte(slot);
+            self.emit_op(OpCode::Pop);
+        } else {
+            self.emit_op_u16(OpCode::DefineGlobal, name);
+        }
+
+        if class.superclass.is_some() {
+            self.end_scope();
+        }
+    }
+}
@@ -0,0 +1,468 @@
+use crate::token::value;
+
+use super::value::*;
+
+use std::collections::HashMap;
+use std::mem;
+
+// Reference to an object on the heap. Handles are only valid as long as the
+// object is reachable from the roots the virtual machine marks, the slot of
+// a collected object is reused by later allocations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Handle(usize);
+
+#[derive(Clone, Copy)]
+pub struct GcConfig {
+    // bytes allocated before the first collection, later thresholds never
+    // go below it
+    pub initial_threshold: usize,
+    // after a collection the next one happens once the live bytes have been
+    // multiplied by this factor
+    pub grow_factor: usize,
+    // collect on every allocation, used to flush out missing roots
+    pub stress: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            initial_threshold: 1024 * 1024,
+            grow_factor: 2,
+            stress: false,
+        }
+    }
+}
+
+struct Entry {
+    obj: Obj,
+    marked: bool,
+}
+
+// Mark and sweep heap holding every object of the virtual machine.
+// Allocating never collects on its own, the virtual machine checks
+// `should_collect` after allocating, marks its roots and calls `collect`.
+pub struct Heap {
+    entries: Vec<Option<Entry>>,
+    // indices of the slots freed by the last sweeps
+    free: Vec<usize>,
+    // every string is interned so that equal strings share one handle
+    strings: HashMap<String, Handle>,
+    bytes_allocated: usize,
+    next_gc: usize,
+    config: GcConfig,
+    // marked objects whose references haven't been traced yet
+    gray: Vec<Handle>,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Heap::new(GcConfig::default())
+    }
+}
+
+impl Heap {
+    pub fn new(config: GcConfig) -> Self {
+        Heap {
+            entries: Vec::new(),
+            free: Vec::new(),
+            strings: HashMap::new(),
+            bytes_allocated: 0,
+            next_gc: config.initial_threshold,
+            config,
+            gray: Vec::new(),
+        }
+    }
+
+    pub fn alloc(&mut self, obj: Obj) -> Handle {
+        self.bytes_allocated += size_of_obj(&obj);
+
+        let entry = Some(Entry { obj, marked: false });
+        match self.free.pop() {
+            Some(index) => {
+                self.entries[index] = entry;
+                Handle(index)
+            },
+            None => {
+                self.entries.push(entry);
+                Handle(self.entries.len() - 1)
+            },
+        }
+    }
+
+    pub fn intern(&mut self, s: &str) -> Handle {
+        if let Some(handle) = self.strings.get(s) {
+            return *handle;
+        }
+
+        let handle = self.alloc(Obj::String(s.to_string()));
+        self.strings.insert(s.to_string(), handle);
+        handle
+    }
+
+    pub fn config(&self) -> GcConfig {
+        self.config
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.config.stress || self.bytes_allocated > self.next_gc
+    }
+
+    pub fn get(&self, handle: Handle) -> &Obj {
+        &self.entries[handle.0].as_ref()
+            .expect("handle to a collected object")
+            .obj
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> &mut Obj {
+        &mut self.entries[handle.0].as_mut()
+            .expect("handle to a collected object")
+            .obj
+    }
+
+    pub fn string(&self, handle: Handle) -> &str {
+        match self.get(handle) {
+            Obj::String(s) => s,
+            _ => unreachable!("handle is not a string"),
+        }
+    }
+
+    pub fn function(&self, handle: Handle) -> &FunctionProto {
+        match self.get(handle) {
+            Obj::Function(function) => function,
+            _ => unreachable!("handle is not a function"),
+        }
+    }
+
+    pub fn closure(&self, handle: Handle) -> &Closure {
+        match self.get(handle) {
+            Obj::Closure(closure) => closure,
+            _ => unreachable!("handle is not a closure"),
+        }
+    }
+
+    pub fn upvalue(&self, handle: Handle) -> &Upvalue {
+        match self.get(handle) {
+            Obj::Upvalue(upvalue) => upvalue,
+            _ => unreachable!("handle is not an upvalue"),
+        }
+    }
+
+    pub fn upvalue_mut(&mut self, handle: Handle) -> &mut Upvalue {
+        match self.get_mut(handle) {
+            Obj::Upvalue(upvalue) => upvalue,
+            _ => unreachable!("handle is not an upvalue"),
+        }
+    }
+
+    pub fn class(&self, handle: Handle) -> &Class {
+        match self.get(handle) {
+            Obj::Class(class) => class,
+            _ => unreachable!("handle is not a class"),
+        }
+    }
+
+    pub fn class_mut(&mut self, handle: Handle) -> &mut Class {
+        match self.get_mut(handle) {
+            Obj::Class(class) => class,
+            _ => unreachable!("handle is not a class"),
+        }
+    }
+
+    // formats a value the same way `token::value::Value` is displayed
+    pub fn format(&self, value: Value) -> String {
+        match value {
+            Value::Number(n) => value::Value::Number(n).to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Nil => String::from("nil"),
+            Value::Obj(handle) => match self.get(handle) {
+                Obj::String(s) => s.clone(),
+                Obj::Function(function) if function.name.is_empty() =>
+                    String::from("<script>"),
+                Obj::Function(function) => format!("<fn {}>", function.name),
+                Obj::Closure(closure) =>
+                    self.format(Value::Obj(closure.function)),
+                Obj::Upvalue(_) => String::from("upvalue"),
+                Obj::Class(class) => class.name.clone(),
+                Obj::Instance(instance) =>
+                    format!("{} instance", self.class(instance.class).name),
+                Obj::BoundMethod(bound) =>
+                    self.format(Value::Obj(bound.method)),
+                Obj::Native(_) => String::from("<native fn>"),
+            },
+        }
+    }
+
+    pub fn mark_value(&mut self, value: Value) {
+        if let Value::Obj(handle) = value {
+            self.mark_object(handle);
+        }
+    }
+
+    pub fn mark_object(&mut self, handle: Handle) {
+        let entry = self.entries[handle.0].as_mut()
+            .expect("handle to a collected object");
+        if !entry.marked {
+            entry.marked = true;
+            self.gray.push(handle);
+        }
+    }
+
+    // traces from the objects marked so far and frees everything else
+    pub fn collect(&mut self) {
+        while let Some(handle) = self.gray.pop() {
+            self.blacken(handle);
+        }
+
+        // the intern table doesn't keep strings alive
+        let entries = &self.entries;
+        self.strings.retain(|_, handle| {
+            entries[handle.0].as_ref().is_some_and(|entry| entry.marked)
+        });
+
+        self.bytes_allocated = 0;
+        for (index, slot) in self.entries.iter_mut().enumerate() {
+            match slot {
+                Some(entry) if entry.marked => {
+                    entry.marked = false;
+                    self.bytes_allocated += size_of_obj(&entry.obj);
+                },
+                Some(_) => {
+                    *slot = None;
+                    self.free.push(index);
+                },
+                None => {},
+            }
+        }
+
+        self.next_gc = (self.bytes_allocated * self.config.grow_factor)
+            .max(self.config.initial_threshold);
+    }
+
+    fn blacken(&mut self, handle: Handle) {
+        let mut values = vec![];
+        let mut handles = vec![];
+
+        match self.get(handle) {
+            Obj::String(_) | Obj::Native(_) => {},
+            Obj::Function(function) => {
+                values.extend(function.chunk.constants.iter().copied());
+            },
+            Obj::Closure(closure) => {
+                handles.push(closure.function);
+                handles.extend(closure.upvalues.iter().copied());
+            },
+            Obj::Upvalue(Upvalue::Closed(value)) => values.push(*value),
+            Obj::Upvalue(Upvalue::Open(_)) => {},
+            Obj::Class(class) => {
+                for (name, method) in &class.methods {
+                    handles.push(*name);
+                    handles.push(*method);
+                }
+            },
+            Obj::Instance(instance) => {
+                handles.push(instance.class);
+                for (name, value) in &instance.fields {
+                    handles.push(*name);
+                    values.push(*value);
+                }
+            },
+            Obj::BoundMethod(bound) => {
+                values.push(bound.receiver);
+                handles.push(bound.method);
+            },
+        }
+
+        for value in values {
+            self.mark_value(value);
+        }
+        for handle in handles {
+            self.mark_object(handle);
+        }
+    }
+}
+
+// rough number of bytes owned by an object, used to pace collections
+fn size_of_obj(obj: &Obj) -> usize {
+    mem::size_of::<Entry>() + match obj {
+        Obj::String(s) => s.capacity(),
+        Obj::Function(function) =>
+            function.chunk.code.capacity()
+                + function.chunk.constants.capacity()
+                    * mem::size_of::<Value>(),
+        Obj::Closure(closure) =>
+            closure.upvalues.capacity() * mem::size_of::<Handle>(),
+        Obj::Class(class) =>
+            class.methods.capacity() * 2 * mem::size_of::<Handle>(),
+        Obj::Instance(instance) =>
+            instance.fields.capacity()
+                * (mem::size_of::<Handle>() + mem::size_of::<Value>()),
+        Obj::Upvalue(_) | Obj::BoundMethod(_) | Obj::Native(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(initial_threshold: usize, grow_factor: usize) -> GcConfig {
+        GcConfig { initial_threshold, grow_factor, stress: false }
+    }
+
+    fn is_live(heap: &Heap, handle: Handle) -> bool {
+        heap.entries[handle.0].is_some()
+    }
+
+    // an instance of a new class with one field set to `value`
+    fn instance(heap: &mut Heap, value: Value) -> Handle {
+        let class = heap.alloc(Obj::Class(Class {
+            name: String::from("A"),
+            methods: HashMap::new(),
+        }));
+        let name = heap.intern("field");
+        let mut fields = HashMap::new();
+        fields.insert(name, value);
+        heap.alloc(Obj::Instance(Instance { class, fields }))
+    }
+
+    #[test]
+    fn alloc_counts_the_bytes() {
+        let mut heap = Heap::default();
+        let a = heap.alloc(Obj::String(String::from("a")));
+        let b = heap.alloc(Obj::String(String::from("b")));
+
+        assert_ne!(a, b);
+        assert_eq!(heap.string(a), "a");
+        assert_eq!(heap.string(b), "b");
+        assert_eq!(heap.bytes_allocated,
+                   size_of_obj(heap.get(a)) + size_of_obj(heap.get(b)));
+    }
+
+    #[test]
+    fn equal_strings_are_interned_once() {
+        let mut heap = Heap::default();
+        let a = heap.intern("name");
+        assert_eq!(heap.intern("name"), a);
+        assert_ne!(heap.intern("other"), a);
+        assert_eq!(heap.entries.len(), 2);
+    }
+
+    #[test]
+    fn collect_frees_what_isnt_marked() {
+        let mut heap = Heap::default();
+        let kept = heap.alloc(Obj::String(String::from("kept")));
+        let freed = heap.alloc(Obj::String(String::from("freed")));
+
+        heap.mark_object(kept);
+        heap.collect();
+        assert!(is_live(&heap, kept));
+        assert!(!is_live(&heap, freed));
+        assert_eq!(heap.bytes_allocated, size_of_obj(heap.get(kept)));
+
+        // the marks are cleared for the next collection
+        heap.collect();
+        assert!(!is_live(&heap, kept));
+        assert_eq!(heap.bytes_allocated, 0);
+    }
+
+    #[test]
+    fn collect_traces_the_references_of_marked_objects() {
+        let mut heap = Heap::default();
+        let value = heap.alloc(Obj::String(String::from("value")));
+        let instance = instance(&mut heap, Value::Obj(value));
+        let class = match heap.get(instance) {
+            Obj::Instance(instance) => instance.class,
+            _ => unreachable!(),
+        };
+        let garbage = heap.alloc(Obj::String(String::from("garbage")));
+
+        heap.mark_value(Value::Obj(instance));
+        heap.collect();
+        for handle in [instance, class, value, heap.intern("field")] {
+            assert!(is_live(&heap, handle));
+        }
+        assert!(!is_live(&heap, garbage));
+    }
+
+    #[test]
+    fn freed_slots_are_reused() {
+        let mut heap = Heap::default();
+        let freed = heap.alloc(Obj::String(String::from("freed")));
+        heap.collect();
+
+        let new = heap.alloc(Obj::String(String::from("new")));
+        assert_eq!(new, freed);
+        assert_eq!(heap.string(new), "new");
+        assert_eq!(heap.entries.len(), 1);
+    }
+
+    #[test]
+    fn collected_strings_leave_the_intern_table() {
+        let mut heap = Heap::default();
+        let kept = heap.intern("kept");
+        heap.intern("freed");
+
+        heap.mark_object(kept);
+        heap.collect();
+        assert!(heap.strings.contains_key("kept"));
+        assert!(!heap.strings.contains_key("freed"));
+
+        // interning it again allocates a new string
+        let freed = heap.intern("freed");
+        assert_eq!(heap.string(freed), "freed");
+        assert_eq!(heap.intern("kept"), kept);
+    }
+
+    #[test]
+    fn next_collection_is_paced_by_the_live_bytes() {
+        let mut heap = Heap::new(config(10, 3));
+        let big = heap.alloc(Obj::String("x".repeat(100)));
+        let live = size_of_obj(heap.get(big));
+
+        heap.mark_object(big);
+        heap.collect();
+        assert_eq!(heap.next_gc, 3 * live);
+
+        // never below the initial threshold
+        let mut heap = Heap::new(config(1024 * 1024, 3));
+        let small = heap.alloc(Obj::String(String::from("x")));
+        heap.mark_object(small);
+        heap.collect();
+        assert_eq!(heap.next_gc, 1024 * 1024);
+    }
+
+    #[test]
+    fn config_changes_when_collections_happen() {
+        let string = || Obj::String(String::from("string"));
+        let size = size_of_obj(&string());
+
+        // the threshold is crossed by the third string
+        let mut heap = Heap::new(config(2 * size, 2));
+        heap.alloc(string());
+        heap.alloc(string());
+        assert!(!heap.should_collect());
+        heap.alloc(string());
+        assert!(heap.should_collect());
+
+        // a larger factor waits longer after a collection
+        for grow_factor in [2, 4] {
+            let mut heap = Heap::new(config(size, grow_factor));
+            let live = heap.alloc(string());
+            heap.alloc(string());
+            heap.mark_object(live);
+            heap.collect();
+
+            let mut allocations = 0;
+            while !heap.should_collect() {
+                heap.alloc(string());
+                allocations += 1;
+            }
+            assert_eq!(allocations, grow_factor);
+        }
+
+        // stress collects after every allocation
+        let mut heap = Heap::new(GcConfig { stress: true, ..config(size, 2) });
+        assert!(heap.should_collect());
+        heap.alloc(string());
+        assert!(heap.should_collect());
+    }
+}
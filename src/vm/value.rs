@@ -0,0 +1,95 @@
+use super::chunk::Chunk;
+use super::heap::{Handle, Heap};
+
+use std::collections::HashMap;
+
+// Values manipulated by the virtual machine. Everything that isn't a plain
+// number, boolean or nil lives on the garbage collected `Heap` and is
+// referred to by handle, so copying a value never copies an object.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+    Obj(Handle),
+    Nil,
+}
+
+impl Value {
+    // false and nil are falsey, everything else is truthy
+    pub fn is_truthy(&self) -> bool {
+        match *self {
+            Value::Nil => false,
+            Value::Bool(b) => b,
+            _ => true
+        }
+    }
+
+    // same rules as `Interpreter::is_equal`, strings are interned so objects
+    // can always be compared by identity
+    pub fn is_equal(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number(u), Value::Number(v)) => (u - v).abs() <= 1e-6,
+            _ => self == other,
+        }
+    }
+}
+
+pub enum Obj {
+    String(String),
+    Function(FunctionProto),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+    Native(Native),
+}
+
+// compiled form of a function declaration or of a top-level statement
+#[derive(Default)]
+pub struct FunctionProto {
+    pub name: String,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+// a variable captured by a closure, it points into the stack while the
+// variable is still in scope and owns the value once it has been closed
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Closure {
+    pub function: Handle,
+    pub upvalues: Vec<Handle>,
+}
+
+pub struct Class {
+    pub name: String,
+    // keyed by the interned method name
+    pub methods: HashMap<Handle, Handle>,
+}
+
+pub struct Instance {
+    pub class: Handle,
+    // keyed by the interned field name
+    pub fields: HashMap<Handle, Value>,
+}
+
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Handle,
+}
+
+// Rust function callable from scripts, the error is reported as a runtime
+// error at the call. It works on the values of the virtual machine, so the
+// natives of the tree-walker, `callable::NativeFn`, can't be registered here.
+pub type NativeFn = fn(&mut Heap, &[Value]) -> Result<Value, String>;
+
+pub struct Native {
+    pub name: String,
+    pub arity: usize,
+    pub function: NativeFn,
+}
@@ -0,0 +1,689 @@
+use crate::diagnostic::diagnostic::{Code, Diagnostic, Diagnostics};
+use crate::interpreter::native;
+use crate::interpreter::output::{self, SharedOutput};
+
+use super::chunk::OpCode;
+use super::heap::{GcConfig, Handle, Heap};
+use super::value::*;
+
+use std::collections::HashMap;
+
+// deepest call nesting before reporting a stack overflow
+const FRAMES_MAX: usize = 4096;
+
+struct CallFrame {
+    closure: Handle,
+    // function of the closure, kept here to avoid a lookup per instruction
+    function: Handle,
+    // offset of the next instruction in the chunk of the closure
+    ip: usize,
+    // index of the stack slot holding the callee, locals follow it
+    slots: usize,
+}
+
+pub struct VmError {
+    pub line: u32,
+    pub message: String,
+}
+
+pub struct VM {
+    heap: Heap,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    // keyed by the interned variable name
+    globals: HashMap<Handle, Value>,
+    // upvalues still pointing into the stack, sorted by slot
+    open_upvalues: Vec<Handle>,
+    // compiled scripts that haven't run yet, they are roots as well
+    scripts: Vec<Handle>,
+    init_string: Handle,
+    // where `print` writes to
+    output: SharedOutput,
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        VM::new(GcConfig::default())
+    }
+}
+
+impl VM {
+    pub fn new(config: GcConfig) -> Self {
+        let mut heap = Heap::new(config);
+        let init_string = heap.intern("init");
+
+        let mut vm = VM {
+            heap,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: HashMap::new(),
+            open_upvalues: Vec::new(),
+            scripts: Vec::new(),
+            init_string,
+            output: output::stdout(),
+        };
+        vm.define_native("clock", 0, clock);
+        vm
+    }
+
+    // exposes a Rust function to scripts as the global `name`, natives given
+    // to `Lox::define_native` only exist on the tree-walker
+    pub fn define_native(&mut self,
+                         name: &str,
+                         arity: usize,
+                         function: NativeFn) {
+        let name_handle = self.heap.intern(name);
+        let native = self.heap.alloc(Obj::Native(Native {
+            name: name.to_string(),
+            arity,
+            function,
+        }));
+        self.globals.insert(name_handle, Value::Obj(native));
+    }
+
+    // redirects `print`, which goes to stdout by default
+    pub fn set_output(&mut self, output: SharedOutput) {
+        self.output = output;
+    }
+
+    // the compiler allocates the functions it produces on this heap
+    pub fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+
+    // every global with its value formatted for printing, in no order
+    pub fn globals(&self) -> Vec<(String, String)> {
+        self.globals.iter()
+            .map(|(name, value)| {
+                (self.heap.string(*name).to_string(), self.heap.format(*value))
+            })
+            .collect()
+    }
+
+    // drops every object and global, natives defined by the host are lost
+    // too and only the built-in ones are defined again. The output is kept.
+    pub fn reset(&mut self) {
+        let output = std::mem::replace(&mut self.output, output::stdout());
+        *self = VM::new(self.heap.config());
+        self.output = output;
+    }
+
+    // runs the scripts produced by `Compiler::compile` in order, a runtime
+    // error only aborts the script it happened in
+    pub fn interpret(&mut self,
+                     scripts: Vec<Handle>,
+                     diagnostics: &mut Diagnostics) {
+        self.scripts = scripts;
+        self.scripts.reverse();
+
+        while let Some(&function) = self.scripts.last() {
+            let closure = self.alloc(Obj::Closure(Closure {
+                function,
+                upvalues: vec![],
+            }));
+            self.scripts.pop();
+
+            self.stack.push(Value::Obj(closure));
+            self.frames.push(CallFrame { closure, function, ip: 0, slots: 0 });
+
+            if let Err(error) = self.run() {
+                diagnostics.report(Diagnostic::error_at_line(
+                    Code::Runtime,
+                    error.line,
+                    error.message
+                ));
+                self.stack.clear();
+                self.frames.clear();
+                self.open_upvalues.clear();
+            }
+        }
+    }
+
+    // allocates an object, collecting garbage first when the heap asks for
+    // it. The new object is treated as a root, so the handles it holds
+    // don't need to be reachable from anywhere else.
+    fn alloc(&mut self, obj: Obj) -> Handle {
+        let handle = self.heap.alloc(obj);
+        if self.heap.should_collect() {
+            self.heap.mark_object(handle);
+            self.collect_garbage();
+        }
+        handle
+    }
+
+    fn collect_garbage(&mut self) {
+        for value in &self.stack {
+            self.heap.mark_value(*value);
+        }
+        for frame in &self.frames {
+            self.heap.mark_object(frame.closure);
+        }
+        for (name, value) in &self.globals {
+            self.heap.mark_object(*name);
+            self.heap.mark_value(*value);
+        }
+        for upvalue in &self.open_upvalues {
+            self.heap.mark_object(*upvalue);
+        }
+        for script in &self.scripts {
+            self.heap.mark_object(*script);
+        }
+        self.heap.mark_object(self.init_string);
+
+        self.heap.collect();
+    }
+
+    fn run(&mut self) -> Result<(), VmError> {
+        loop {
+            match OpCode::from_byte(self.read_byte()) {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.stack.push(constant);
+                },
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.stack.pop();
+                },
+                OpCode::GetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack.push(self.stack[slot]);
+                },
+                OpCode::SetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack[slot] = *self.peek(0);
+                },
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    match self.globals.get(&name) {
+                        Some(value) => self.stack.push(*value),
+                        None => return Err(self.error(format!(
+                            "Undefined variable '{}'.",
+                            self.heap.string(name)
+                        ))),
+                    }
+                },
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    let value = self.stack.pop().unwrap();
+                    self.globals.insert(name, value);
+                },
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    let value = *self.peek(0);
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => return Err(self.error(format!(
+                            "Undefined variable '{}'.",
+                            self.heap.string(name)
+                        ))),
+                    }
+                },
+                OpCode::GetUpvalue => {
+                    let slot = self.read_byte() as usize;
+                    let upvalue = self.upvalue_handle(slot);
+                    let value = match self.heap.upvalue(upvalue) {
+                        Upvalue::Open(index) => self.stack[*index],
+                        Upvalue::Closed(value) => *value,
+                    };
+                    self.stack.push(value);
+                },
+                OpCode::SetUpvalue => {
+                    let slot = self.read_byte() as usize;
+                    let upvalue = self.upvalue_handle(slot);
+                    let value = *self.peek(0);
+                    match self.heap.upvalue_mut(upvalue) {
+                        Upvalue::Open(index) => self.stack[*index] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    };
+                },
+                OpCode::GetProperty => {
+                    let name = self.read_string();
+                    let receiver = *self.peek(0);
+                    let (class, field) = match self.instance(receiver) {
+                        Some(instance) => (
+                            instance.class,
+                            instance.fields.get(&name).copied(),
+                        ),
+                        None => return Err(self.error(
+                            String::from("Only instances have properties.")
+                        )),
+                    };
+
+                    // the receiver stays on the stack while the method is
+                    // bound
+                    let value = match field {
+                        Some(value) => value,
+                        None => self.bind_method(class, name, receiver)?,
+                    };
+                    self.stack.pop();
+                    self.stack.push(value);
+                },
+                OpCode::SetProperty => {
+                    let name = self.read_string();
+                    let value = *self.peek(0);
+                    match self.peek(1) {
+                        Value::Obj(handle) => match self.heap.get_mut(*handle) {
+                            Obj::Instance(instance) => {
+                                instance.fields.insert(name, value);
+                            },
+                            _ => return Err(self.error(
+                                String::from("Only instances have fields.")
+                            )),
+                        },
+                        _ => return Err(self.error(
+                            String::from("Only instances have fields.")
+                        )),
+                    }
+
+                    self.stack.pop();
+                    self.stack.pop();
+                    self.stack.push(value);
+                },
+                OpCode::GetSuper => {
+                    let name = self.read_string();
+                    let superclass = match self.peek(0) {
+                        Value::Obj(class) => *class,
+                        _ => unreachable!("'super' is always bound to a class"),
+                    };
+                    let receiver = *self.peek(1);
+                    let method = self.bind_method(superclass, name, receiver)?;
+                    self.stack.pop();
+                    self.stack.pop();
+                    self.stack.push(method);
+                },
+                OpCode::Equal => {
+                    let b = self.stack.pop().unwrap();
+                    let a = self.stack.pop().unwrap();
+                    self.stack.push(Value::Bool(a.is_equal(&b)));
+                },
+                OpCode::NotEqual => {
+                    let b = self.stack.pop().unwrap();
+                    let a = self.stack.pop().unwrap();
+                    self.stack.push(Value::Bool(!a.is_equal(&b)));
+                },
+                op @ OpCode::Greater
+                | op @ OpCode::GreaterEqual
+                | op @ OpCode::Less
+                | op @ OpCode::LessEqual
+                | op @ OpCode::Add
+                | op @ OpCode::Subtract
+                | op @ OpCode::Multiply
+                | op @ OpCode::Divide => self.binary_op(op)?,
+                OpCode::Not => {
+                    let value = self.stack.pop().unwrap();
+                    self.stack.push(Value::Bool(!value.is_truthy()));
+                },
+                OpCode::Negate => match self.stack.pop().unwrap() {
+                    Value::Number(n) => self.stack.push(Value::Number(-n)),
+                    _ => return Err(self.error(String::from(
+                        "Only number can be operated on with unary MINUS \
+                         opeartor"
+                    ))),
+                },
+                OpCode::Print => {
+                    let value = self.stack.pop().unwrap();
+                    let text = self.heap.format(value);
+                    self.output.borrow_mut().print(&text);
+                },
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip += offset;
+                },
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    if !self.peek(0).is_truthy() {
+                        self.frame_mut().ip += offset;
+                    }
+                },
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip -= offset;
+                },
+                OpCode::Call => {
+                    let argc = self.read_byte() as usize;
+                    self.call_value(argc)?;
+                },
+                OpCode::Closure => {
+                    let function = match self.read_constant() {
+                        Value::Obj(function) => function,
+                        _ => unreachable!("closure operand is a function"),
+                    };
+
+                    let upvalue_count = self.heap.function(function)
+                        .upvalue_count;
+                    let mut upvalues = Vec::with_capacity(upvalue_count);
+                    for _ in 0..upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        upvalues.push(if is_local {
+                            self.capture_upvalue(self.frame().slots + index)
+                        } else {
+                            self.upvalue_handle(index)
+                        });
+                    }
+
+                    let closure = self.alloc(Obj::Closure(Closure {
+                        function,
+                        upvalues,
+                    }));
+                    self.stack.push(Value::Obj(closure));
+                },
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                },
+                OpCode::Return => {
+                    let result = self.stack.pop().unwrap();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(result);
+                },
+                OpCode::Class => {
+                    let name = self.read_string();
+                    let class = self.alloc(Obj::Class(Class {
+                        name: self.heap.string(name).to_string(),
+                        methods: HashMap::new(),
+                    }));
+                    self.stack.push(Value::Obj(class));
+                },
+                OpCode::Inherit => {
+                    let superclass = match self.peek(1) {
+                        Value::Obj(handle) => match self.heap.get(*handle) {
+                            Obj::Class(class) => class.methods.clone(),
+                            _ => return Err(self.error(
+                                String::from("Superclass must be a class.")
+                            )),
+                        },
+                        _ => return Err(self.error(
+                            String::from("Superclass must be a class.")
+                        )),
+                    };
+                    // methods are copied down, overriding ones are added
+                    // afterwards
+                    if let Value::Obj(class) = *self.peek(0) {
+                        self.heap.class_mut(class).methods.extend(superclass);
+                    }
+                },
+                OpCode::Method => {
+                    let name = self.read_string();
+                    let method = match self.peek(0) {
+                        Value::Obj(closure) => *closure,
+                        _ => unreachable!("method operand is a closure"),
+                    };
+                    if let Value::Obj(class) = *self.peek(1) {
+                        self.heap.class_mut(class).methods.insert(name, method);
+                    }
+                    self.stack.pop();
+                },
+                OpCode::Interpolate => {
+                    let count = self.read_byte() as usize;
+                    let first = self.stack.len() - count;
+                    let string: String = self.stack[first..].iter()
+                        .map(|part| self.heap.format(*part))
+                        .collect();
+                    self.stack.truncate(first);
+
+                    let handle = self.intern(&string);
+                    self.stack.push(Value::Obj(handle));
+                },
+            }
+        }
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().unwrap()
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frames.last_mut().unwrap();
+        let byte = self.heap.function(frame.function).chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let high = self.read_byte() as u16;
+        let low = self.read_byte() as u16;
+        (high << 8) | low
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_u16() as usize;
+        self.heap.function(self.frame().function).chunk.constants[index]
+    }
+
+    fn read_string(&mut self) -> Handle {
+        match self.read_constant() {
+            Value::Obj(name) => name,
+            _ => unreachable!("name operand is a string constant"),
+        }
+    }
+
+    fn upvalue_handle(&self, slot: usize) -> Handle {
+        self.heap.closure(self.frame().closure).upvalues[slot]
+    }
+
+    fn instance(&self, value: Value) -> Option<&Instance> {
+        match value {
+            Value::Obj(handle) => match self.heap.get(handle) {
+                Obj::Instance(instance) => Some(instance),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn error(&self, message: String) -> VmError {
+        let frame = self.frame();
+        VmError {
+            line: self.heap.function(frame.function).chunk.line(frame.ip - 1),
+            message,
+        }
+    }
+
+    fn binary_op(&mut self, op: OpCode) -> Result<(), VmError> {
+        let result = match (*self.peek(1), *self.peek(0)) {
+            (Value::Number(a), Value::Number(b)) => match op {
+                OpCode::Greater => Value::Bool(a > b),
+                OpCode::GreaterEqual => Value::Bool(a >= b),
+                OpCode::Less => Value::Bool(a < b),
+                OpCode::LessEqual => Value::Bool(a <= b),
+                OpCode::Add => Value::Number(a + b),
+                OpCode::Subtract => Value::Number(a - b),
+                OpCode::Multiply => Value::Number(a * b),
+                _ => Value::Number(a / b),
+            },
+            (Value::Obj(a), Value::Obj(b)) => {
+                match (self.heap.get(a), self.heap.get(b)) {
+                    (Obj::String(a), Obj::String(b)) if op == OpCode::Add => {
+                        let mut concatenated =
+                            String::with_capacity(a.len() + b.len());
+                        concatenated.push_str(a);
+                        concatenated.push_str(b);
+                        Value::Obj(self.intern(&concatenated))
+                    },
+                    (Obj::String(_), Obj::String(_)) => return Err(
+                        self.error(String::from(
+                            "Operator not supported on type Strings"
+                        ))
+                    ),
+                    _ => return Err(
+                        self.error(String::from("Invalid operation"))
+                    ),
+                }
+            },
+            _ => return Err(self.error(String::from("Invalid operation"))),
+        };
+
+        self.stack.pop();
+        self.stack.pop();
+        self.stack.push(result);
+        Ok(())
+    }
+
+    // interns a string created at runtime, collecting garbage if needed
+    fn intern(&mut self, s: &str) -> Handle {
+        let handle = self.heap.intern(s);
+        if self.heap.should_collect() {
+            self.heap.mark_object(handle);
+            self.collect_garbage();
+        }
+        handle
+    }
+
+    fn call_value(&mut self, argc: usize) -> Result<(), VmError> {
+        let callee_slot = self.stack.len() - argc - 1;
+
+        let callee = match self.stack[callee_slot] {
+            Value::Obj(handle) => handle,
+            _ => return Err(self.error(
+                String::from("Can only call functions and classes.")
+            )),
+        };
+
+        match self.heap.get(callee) {
+            Obj::Closure(_) => self.call(callee, argc),
+            Obj::BoundMethod(bound) => {
+                // the receiver takes the place of the callee as `this`
+                let method = bound.method;
+                self.stack[callee_slot] = bound.receiver;
+                self.call(method, argc)
+            },
+            Obj::Native(native) => {
+                if argc != native.arity {
+                    return Err(self.error(format!(
+                        "Expected {} arguments but got {}.",
+                        native.arity,
+                        argc
+                    )));
+                }
+
+                let function = native.function;
+                let arguments = self.stack[callee_slot + 1..].to_vec();
+                let result = function(&mut self.heap, &arguments)
+                    .map_err(|message| self.error(message))?;
+                self.stack.truncate(callee_slot);
+                self.stack.push(result);
+                Ok(())
+            },
+            Obj::Class(class) => {
+                let initializer = class.methods.get(&self.init_string)
+                    .copied();
+                let instance = self.alloc(Obj::Instance(Instance {
+                    class: callee,
+                    fields: HashMap::new(),
+                }));
+                self.stack[callee_slot] = Value::Obj(instance);
+
+                match initializer {
+                    Some(initializer) => self.call(initializer, argc),
+                    None if argc != 0 => Err(self.error(format!(
+                        "Expected 0 arguments but got {}.", argc
+                    ))),
+                    None => Ok(()),
+                }
+            },
+            _ => Err(self.error(
+                String::from("Can only call functions and classes.")
+            )),
+        }
+    }
+
+    fn call(&mut self, closure: Handle, argc: usize) -> Result<(), VmError> {
+        let function = self.heap.closure(closure).function;
+        let arity = self.heap.function(function).arity;
+        if argc != arity {
+            return Err(self.error(format!(
+                "Expected {} arguments but got {}.",
+                arity,
+                argc
+            )));
+        }
+
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.error(String::from("Stack overflow.")));
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            function,
+            ip: 0,
+            slots: self.stack.len() - argc - 1,
+        });
+        Ok(())
+    }
+
+    fn bind_method(&mut self,
+                   class: Handle,
+                   name: Handle,
+                   receiver: Value) -> Result<Value, VmError> {
+        match self.heap.class(class).methods.get(&name).copied() {
+            Some(method) => {
+                let bound = self.alloc(Obj::BoundMethod(BoundMethod {
+                    receiver,
+                    method,
+                }));
+                Ok(Value::Obj(bound))
+            },
+            None => Err(self.error(format!(
+                "Undefined property '{}'.",
+                self.heap.string(name)
+            ))),
+        }
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Handle {
+        let heap = &self.heap;
+        let position = self.open_upvalues.iter().position(|upvalue| {
+            matches!(heap.upvalue(*upvalue), Upvalue::Open(index)
+                     if *index >= slot)
+        });
+
+        if let Some(position) = position {
+            let existing = self.open_upvalues[position];
+            if matches!(heap.upvalue(existing), Upvalue::Open(index)
+                        if *index == slot) {
+                return existing;
+            }
+        }
+
+        let upvalue = self.alloc(Obj::Upvalue(Upvalue::Open(slot)));
+        let position = position.unwrap_or(self.open_upvalues.len());
+        self.open_upvalues.insert(position, upvalue);
+        upvalue
+    }
+
+    // moves the values of every upvalue pointing at `from` or above off the
+    // stack
+    fn close_upvalues(&mut self, from: usize) {
+        while let Some(&upvalue) = self.open_upvalues.last() {
+            let index = match self.heap.upvalue(upvalue) {
+                Upvalue::Open(index) if *index >= from => *index,
+                _ => break,
+            };
+
+            *self.heap.upvalue_mut(upvalue) =
+                Upvalue::Closed(self.stack[index]);
+            self.open_upvalues.pop();
+        }
+    }
+}
+
+fn clock(_heap: &mut Heap, _arguments: &[Value]) -> Result<Value, String> {
+    native::seconds_since_epoch().map(Value::Number)
+}
@@ -0,0 +1,184 @@
+use rulox::diagnostic::diagnostic::{Code, Diagnostics};
+use rulox::interpreter::interpreter::{Interpreter, InterpreterError};
+use rulox::vm::{compiler::Compiler, heap::Heap, value, vm::VM};
+use rulox::{lox, Lox, LoxError, Value};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread;
+
+fn vm_square(_: &mut Heap, args: &[value::Value])
+    -> Result<value::Value, String> {
+    match args[0] {
+        value::Value::Number(n) => Ok(value::Value::Number(n * n)),
+        _ => Err(String::from("Operand must be a number.")),
+    }
+}
+
+fn square(_: &mut Interpreter, args: &[Value])
+    -> Result<Value, InterpreterError> {
+    match args[0] {
+        Value::Number(n) => Ok(Value::Number(n * n)),
+        _ => Err(InterpreterError::native("Operand must be a number.")),
+    }
+}
+
+// `Lox` printing into the returned lines
+fn capturing() -> (Lox, Rc<RefCell<Vec<String>>>) {
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let mut lox = Lox::new();
+    lox.set_output(lines.clone());
+    (lox, lines)
+}
+
+#[test]
+fn eval_gives_the_value_of_the_last_expression() {
+    let mut lox = Lox::new();
+    assert_eq!(lox.eval("1 + 2;"), Ok(Value::Number(3.0)));
+    assert_eq!(lox.eval("var a = 1;"), Ok(Value::Nil));
+    assert_eq!(lox.eval("\"a\" + \"b\";"), Ok(Value::String("ab".into())));
+}
+
+#[test]
+fn globals_persist_across_calls() {
+    let mut lox = Lox::new();
+    lox.eval("fun square(n) { return n * n; }").unwrap();
+    assert_eq!(lox.eval("square(3);"), Ok(Value::Number(9.0)));
+    assert!(matches!(lox.get_global("square"), Some(Value::Function(_))));
+}
+
+#[test]
+fn host_reads_and_writes_globals() {
+    let mut lox = Lox::new();
+    lox.set_global("x", Value::Number(4.0));
+    lox.eval("var y = x * 2; x = 1;").unwrap();
+
+    assert_eq!(lox.get_global("x"), Some(Value::Number(1.0)));
+    assert_eq!(lox.get_global("y"), Some(Value::Number(8.0)));
+    assert_eq!(lox.get_global("z"), None);
+}
+
+#[test]
+fn natives_are_callable_from_scripts() {
+    let mut lox = Lox::new();
+    lox.define_native("square", 1, square);
+    assert_eq!(lox.eval("square(5);"), Ok(Value::Number(25.0)));
+
+    // errors raised by a native are reported at the call
+    match lox.eval("\nsquare(\"a\");") {
+        Err(LoxError::Runtime(diagnostic)) => {
+            assert_eq!(diagnostic.message, "Operand must be a number.");
+            assert_eq!(diagnostic.span.line, 2);
+        },
+        result => panic!("expected a runtime error, got {:?}", result),
+    }
+}
+
+#[test]
+fn print_goes_to_the_output() {
+    let (mut lox, lines) = capturing();
+    lox.eval("print 1 + 2; print \"done\";").unwrap();
+    assert_eq!(*lines.borrow(), vec!["3", "done"]);
+}
+
+#[test]
+fn compile_errors_are_all_returned() {
+    let mut lox = Lox::new();
+    match lox.eval("var = 1;\nprint ;") {
+        Err(LoxError::Compile(diagnostics)) => {
+            let messages: Vec<_> = diagnostics.iter()
+                .map(|diagnostic| &diagnostic.message[..])
+                .collect();
+            assert_eq!(messages, vec!["Expect variable name.",
+                                      "Expect expression."]);
+        },
+        result => panic!("expected compile errors, got {:?}", result),
+    }
+}
+
+#[test]
+fn eval_stops_at_a_runtime_error() {
+    let (mut lox, lines) = capturing();
+    let error = lox.eval("print 1; print nope; print 2;").unwrap_err();
+    assert_eq!(error.to_string(), "[line 1]: Undefined variable 'nope'.");
+    assert_eq!(*lines.borrow(), vec!["1"]);
+}
+
+#[test]
+fn run_reports_and_continues_after_a_runtime_error() {
+    let (mut lox, lines) = capturing();
+    let mut diagnostics = Diagnostics::new();
+    lox.run("print 1; print nope; print 2;", &mut diagnostics);
+
+    assert!(diagnostics.has_runtime_errors());
+    assert_eq!(diagnostics.iter().next().unwrap().code, Code::Runtime);
+    assert_eq!(*lines.borrow(), vec!["1", "2"]);
+}
+
+// runs a script on the virtual machine, returning what it printed
+fn run_vm(vm: &mut VM, source: &str, diagnostics: &mut Diagnostics)
+    -> Vec<String> {
+    let lines = Rc::new(RefCell::new(Vec::<String>::new()));
+    vm.set_output(lines.clone());
+
+    let stmts = lox::parse(source, diagnostics).unwrap();
+    let scripts = Compiler::new(vm.heap_mut(), diagnostics).compile(&stmts);
+    vm.interpret(scripts, diagnostics);
+
+    lines.take()
+}
+
+#[test]
+fn vm_print_goes_to_the_output() {
+    let mut diagnostics = Diagnostics::new();
+    let printed = run_vm(&mut VM::default(),
+                         "print 1 + 2; print \"a\" + \"b\";",
+                         &mut diagnostics);
+
+    assert!(diagnostics.is_empty());
+    assert_eq!(printed, vec!["3", "ab"]);
+}
+
+#[test]
+fn vm_natives_are_callable_from_scripts() {
+    let mut vm = VM::default();
+    vm.define_native("square", 1, vm_square);
+
+    let mut diagnostics = Diagnostics::new();
+    let printed = run_vm(&mut vm, "print square(5);\nprint square(nil);",
+                         &mut diagnostics);
+
+    assert_eq!(printed, vec!["25"]);
+    let errors: Vec<_> = diagnostics.iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect();
+    assert_eq!(errors, vec!["[line 2]: Operand must be a number."]);
+}
+
+#[test]
+fn both_backends_have_clock() {
+    let mut lox = Lox::new();
+    assert!(matches!(lox.eval("clock();"), Ok(Value::Number(n)) if n > 0.0));
+
+    let mut diagnostics = Diagnostics::new();
+    let printed = run_vm(&mut VM::default(), "print clock() > 0;",
+                         &mut diagnostics);
+    assert_eq!(printed, vec!["true"]);
+}
+
+#[test]
+fn deep_recursion_is_a_runtime_error() {
+    // the default stack size of a spawned thread
+    let thread = thread::Builder::new().stack_size(2 * 1024 * 1024);
+    let recurse = thread.spawn(|| {
+        let mut lox = Lox::new();
+        let error = lox.eval("fun f(n) {\n  if (n == 0) return 0;\n  \
+                              return 1 + f(n - 1);\n}\nf(100000);")
+            .unwrap_err();
+        assert_eq!(error.to_string(), "[line 3]: Stack overflow.");
+
+        // the next script starts from an empty stack again
+        assert_eq!(lox.eval("f(100);"), Ok(Value::Number(100.0)));
+    });
+    recurse.unwrap().join().unwrap();
+}
@@ -0,0 +1,44 @@
+use rulox::harness::runner::Runner;
+
+use std::path::{Path, PathBuf};
+
+// runs every script under test/ through the interpreter binary the way
+// `rulox test test` does, failing with what each failing script got wrong
+fn run_suite(args: &[&str], vm: bool) {
+    let program = PathBuf::from(env!("CARGO_BIN_EXE_rust_lox"));
+    let args = args.iter().map(|arg| arg.to_string()).collect();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+
+    let outcomes = Runner::new(program, args, vm).run_dir(&dir)
+        .expect("the test scripts can be run");
+    assert!(!outcomes.is_empty());
+
+    let failures: Vec<String> = outcomes.iter()
+        .filter(|outcome| !outcome.passed())
+        .map(|outcome| {
+            format!("{}\n    {}", outcome.path.display(),
+                    outcome.failures.join("\n    "))
+        })
+        .collect();
+    assert!(failures.is_empty(), "failed:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn tree_walker() {
+    run_suite(&[], false);
+}
+
+#[test]
+fn tree_walker_gc_stress() {
+    run_suite(&["--gc-stress"], false);
+}
+
+#[test]
+fn vm() {
+    run_suite(&[], true);
+}
+
+#[test]
+fn vm_gc_stress() {
+    run_suite(&["--gc-stress"], true);
+}
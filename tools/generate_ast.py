@@ -4,21 +4,49 @@ import sys
 
 base_productions = {
     "Expr": [
-      "Assign   : Token name, Expr value",
+      "Assign   : Token name, Expr value, Cell<Option<Slot>> slot",
       "Binary   : Expr left, Token operator, Expr right",
+      "Call     : Expr callee, Token paren, Vec<Box<Expr>> arguments",
+      "Get      : Expr object, Token name",
       "Grouping : Expr expression",
+      "Interpolation : Vec<Box<Expr>> parts",
       "Literal  : Token value",
+      "Logical  : Expr left, Token operator, Expr right",
+      "Set      : Expr object, Token name, Expr value",
+      "Super    : Token keyword, Token method, Cell<Option<Slot>> slot",
+      "This     : Token keyword, Cell<Option<Slot>> slot",
       "Unary    : Token operator, Expr right",
-      "Variable : Token name"
+      "Variable : Token name, Cell<Option<Slot>> slot"
     ],
     "Stmt": [
         "Expression : Expr expression",
         "Print : Expr expression",
-        "Var : Token name, Option<Box<Expr>> initializer"
+        "Var : Token name, Option<Box<Expr>> initializer",
+        "Block : Vec<Stmt> statements",
+        "If : Expr condition, Box<Stmt> then_branch, Option<Box<Stmt>> else_branch",
+        "While : Expr condition, Box<Stmt> body, Option<Box<Expr>> increment",
+        "Break : Token keyword",
+        "Continue : Token keyword",
+        "Function : Token name, Vec<Token> params, Rc<Vec<Stmt>> body",
+        "Return : Token keyword, Option<Box<Expr>> value",
+        "Class : Token name, Option<Box<Expr>> superclass, Vec<Function> methods"
     ]
 }
 
 
+# node names that clash with rust keywords once lowercased, `super` can't
+# even be a raw identifier so these get a trailing underscore instead
+RUST_KEYWORDS = {"if", "while", "for", "return", "break", "continue",
+                 "super", "fn", "loop", "match", "type"}
+
+
+def get_param_name(node_type):
+    name = node_type.lower()
+    if name in RUST_KEYWORDS:
+        return name + "_"
+    return name
+
+
 def get_visitor_name(base_name):
     if base_name == "Expr":
         return "Visitor"
@@ -34,7 +62,7 @@ def define_visitor(f, base_name, types):
         f.write("    fn visit_{} (&mut self, {}: &{}) -> Self::R;\n"
                 .format(
                         node_type.lower(),
-                        node_type.lower(),
+                        get_param_name(node_type),
                         node_type
                     ))
     f.write("}\n\n")
@@ -51,11 +79,24 @@ visitor: &mut V) -> V::R {{\n".format(
         f.write("            {}::{}({}) => visitor.visit_{}({}),\n".format(
                 base_name,
                 node_type,
+                get_param_name(node_type),
                 node_type.lower(),
-                node_type.lower(),
-                node_type.lower()))
+                get_param_name(node_type)))
 
     f.write("        }\n")
+    f.write("    }\n\n")
+
+    # every node records the region of the source it was parsed from
+    f.write("    pub fn span(&self) -> Span {\n")
+    f.write("        match self {\n")
+    for node_type in types:
+        node_type = node_type.strip().split(":")[0].strip()
+        f.write("            {}::{}({}) => {}.span,\n".format(
+                base_name,
+                node_type,
+                get_param_name(node_type),
+                get_param_name(node_type)))
+    f.write("        }\n")
     f.write("    }\n")
     f.write("}\n\n")
 
@@ -66,7 +107,11 @@ def main():
         sys.exit(1)
 
     with open(sys.argv[1] + "/ast/node.rs", "w") as f:
+        f.write("use crate::ast::slot::Slot;\n")
+        f.write("use crate::token::span::Span;\n")
         f.write("use crate::token::token::Token;\n\n")
+        f.write("use std::cell::Cell;\n")
+        f.write("use std::rc::Rc;\n\n")
 
         for base_class, productions in base_productions.items():
             f.write("pub enum {} {{\n".format(base_class))
@@ -96,6 +141,7 @@ def main():
                         param.strip().split(" ")[1].strip(),
                         ptr_type
                     ))
+                f.write("    pub span: Span,\n")
                 f.write("}\n\n")
 
                 # implement types using visitor patter